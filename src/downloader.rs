@@ -1,5 +1,8 @@
 use reqwest::{Error, RequestBuilder};
 
+/// Browser user agent for sites that reject unknown clients
+pub const UA_ERR: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36";
+
 pub async fn download(v: RequestBuilder) -> Result<String, Error> {
     for i in 0..5 {
         let data = match v.try_clone().unwrap().send().await {
@@ -1,7 +1,9 @@
+use crate::pages::mangaupdates::query::QueryError;
 use api_structure::error::{ApiErr, ApiErrorType};
 use base64::DecodeError;
 use js_sandbox::JsError;
 use openssl::error::ErrorStack;
+use pg_embed::pg_errors::PgEmbedError;
 use std::io;
 use std::io::Error;
 use std::num::ParseFloatError;
@@ -69,6 +71,14 @@ impl ScrapeError {
             err_type: ApiErrorType::ScrapeErrorInputError,
         })
     }
+
+    pub fn timeout(msg: impl ToString) -> Self {
+        ScrapeError(ApiErr {
+            message: Some(msg.to_string()),
+            cause: Some("timed out".to_string()),
+            err_type: ApiErrorType::ScrapeErrorFetchError,
+        })
+    }
 }
 
 impl From<io::Error> for ScrapeError {
@@ -130,3 +140,33 @@ impl From<std::num::ParseIntError> for ScrapeError {
         })
     }
 }
+
+impl From<tokio_postgres::Error> for ScrapeError {
+    fn from(error: tokio_postgres::Error) -> Self {
+        ScrapeError(ApiErr {
+            message: Some("Failed to query database".to_string()),
+            cause: Some(error.to_string()),
+            err_type: ApiErrorType::InternalError,
+        })
+    }
+}
+
+impl From<PgEmbedError> for ScrapeError {
+    fn from(error: PgEmbedError) -> Self {
+        ScrapeError(ApiErr {
+            message: Some("Failed to run embedded postgres".to_string()),
+            cause: Some(error.to_string()),
+            err_type: ApiErrorType::InternalError,
+        })
+    }
+}
+
+impl From<QueryError> for ScrapeError {
+    fn from(error: QueryError) -> Self {
+        ScrapeError(ApiErr {
+            message: Some("Invalid query".to_string()),
+            cause: Some(error.to_string()),
+            err_type: ApiErrorType::ScrapeErrorInputError,
+        })
+    }
+}
@@ -0,0 +1,177 @@
+use regex::{Captures, Regex};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+struct Patterns {
+    iso: Regex,
+    month_first: Regex,
+    day_first: Regex,
+    relative: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        iso: Regex::new(
+            r"(\d{4})[-/.](\d{1,2})[-/.](\d{1,2})(?:[t ](\d{1,2}):(\d{2})(?::(\d{2}))?(?:\.\d+)?)?\s*(z|[+-]\d{2}:?\d{2})?",
+        )
+        .unwrap(),
+        month_first: Regex::new(r"([a-z]{3,9})\.?\s+(\d{1,2})(?:st|nd|rd|th)?,?\s+(\d{4})").unwrap(),
+        // rfc 2822 as used by rss, `tue, 21 may 2024 10:00:00 +0000`
+        day_first: Regex::new(
+            r"(\d{1,2})(?:st|nd|rd|th)?\s+([a-z]{3,9})\.?,?\s+(\d{4})(?:\s+(\d{1,2}):(\d{2})(?::(\d{2}))?)?\s*(z|gmt|utc|[+-]\d{2}:?\d{2})?",
+        )
+        .unwrap(),
+        relative: Regex::new(r"(\d+)\s*(second|sec|minute|min|hour|hr|day|week|month)s?").unwrap(),
+    })
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Unix seconds of dates like `2024-05-01T12:00:00Z`, `May 1, 2024`, `1 May 2024`,
+/// unix timestamps and countdowns like `in 3 days` or `2 hours left`.
+/// Times without a timezone are treated as utc. Dates without a time start with the first
+/// timezone to reach that day, so a chapter released on that day counts as released all day
+pub fn parse_date(s: &str, now: i64) -> Option<i64> {
+    let s = s.trim().to_lowercase();
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+        let v: i64 = s.parse().ok()?;
+        // milliseconds
+        return Some(match s.len() > 11 {
+            true => v / 1000,
+            false => v,
+        });
+    }
+    let p = patterns();
+    if let Some(c) = p.iso.captures(&s) {
+        let num = |i: usize| c.get(i).and_then(|v| v.as_str().parse::<i64>().ok());
+        let day = timestamp(num(1)?, num(2)?, num(3)?)?;
+        return with_time(day, &c, 4);
+    }
+    if let Some(c) = p.month_first.captures(&s) {
+        let day = timestamp(c[3].parse().ok()?, month(&c[1])?, c[2].parse().ok()?)?;
+        return Some(day - EARLIEST_OFFSET);
+    }
+    if let Some(c) = p.day_first.captures(&s) {
+        let day = timestamp(c[3].parse().ok()?, month(&c[2])?, c[1].parse().ok()?)?;
+        return with_time(day, &c, 4);
+    }
+    if let Some(c) = p.relative.captures(&s) {
+        let amount: i64 = c[1].parse().ok()?;
+        let unit = match &c[2] {
+            "second" | "sec" => 1,
+            "minute" | "min" => 60,
+            "hour" | "hr" => 3600,
+            "day" => 86400,
+            "week" => 604800,
+            _ => 2592000,
+        };
+        // `3 days` is as much a past date as `3 days ago`,
+        // only `in 3 days` and countdowns like `3 days left` are in the future
+        let (before, after) = (&s[..c.get(0)?.start()], &s[c.get(0)?.end()..]);
+        let future = before.split_whitespace().last() == Some("in")
+            || matches!(after.split_whitespace().next(), Some("left" | "remaining"));
+        let sign = match future {
+            true => 1,
+            false => -1,
+        };
+        return Some(now + sign * amount * unit);
+    }
+    None
+}
+
+/// UTC+14, the first timezone to start a day
+const EARLIEST_OFFSET: i64 = 14 * 3600;
+
+/// adds hours, minutes, seconds and the utc offset starting at the capture group `start`
+fn with_time(day: i64, c: &Captures, start: usize) -> Option<i64> {
+    if c.get(start).is_none() {
+        return Some(day - EARLIEST_OFFSET);
+    }
+    let num = |i: usize| c.get(i).and_then(|v| v.as_str().parse::<i64>().ok());
+    let mut time = day
+        + num(start).unwrap_or(0) * 3600
+        + num(start + 1).unwrap_or(0) * 60
+        + num(start + 2).unwrap_or(0);
+    if let Some(offset) = c.get(start + 3).map(|v| v.as_str().replace(':', "")) {
+        if offset.starts_with(['+', '-']) {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let hours: i64 = offset[1..3].parse().ok()?;
+            let minutes: i64 = offset[3..5].parse().ok()?;
+            time -= sign * (hours * 3600 + minutes * 60);
+        }
+    }
+    Some(time)
+}
+
+fn month(s: &str) -> Option<i64> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    MONTHS
+        .iter()
+        .position(|v| s.starts_with(v))
+        .map(|v| v as i64 + 1)
+}
+
+/// unix seconds of midnight(utc) of a gregorian date
+fn timestamp(year: i64, month: i64, day: i64) -> Option<i64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some((era * 146097 + day_of_era - 719468) * 86400)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_dates() {
+        let now = 1_000_000;
+        assert_eq!(parse_date("3 days ago", now), Some(now - 3 * 86400));
+        assert_eq!(parse_date("3 days", now), Some(now - 3 * 86400));
+        assert_eq!(parse_date("Updated 2 hours", now), Some(now - 7200));
+        assert_eq!(parse_date("in 3 days", now), Some(now + 3 * 86400));
+        assert_eq!(parse_date("Free in 5 hours", now), Some(now + 5 * 3600));
+        assert_eq!(parse_date("2 hours left", now), Some(now + 7200));
+    }
+
+    #[test]
+    fn absolute_dates() {
+        assert_eq!(parse_date("2024-05-01T12:00:00Z", 0), Some(1714564800));
+        assert_eq!(parse_date("2024-05-01 12:00", 0), Some(1714564800));
+        assert_eq!(
+            parse_date("Wed, 01 May 2024 12:00:00 +0200", 0),
+            Some(1714557600)
+        );
+        assert_eq!(parse_date("1714564800000", 0), Some(1714564800));
+    }
+
+    #[test]
+    fn dates_without_time_last_all_day() {
+        // 2024-05-01 00:00 in UTC+14
+        let start = 1714521600 - 14 * 3600;
+        for s in [
+            "2024-05-01",
+            "2024/5/1",
+            "May 1, 2024",
+            "1st May 2024",
+            "01 May 2024",
+        ] {
+            assert_eq!(parse_date(s, 0), Some(start), "{s}");
+        }
+        assert_eq!(parse_date("2024-05-01 00:00", 0), Some(1714521600));
+    }
+}
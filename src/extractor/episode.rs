@@ -0,0 +1,295 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EpisodeKind {
+    #[default]
+    Regular,
+    /// extras, specials, prologues and epilogues
+    Extra,
+    Omake,
+    Oneshot,
+}
+
+/// Everything that can be read from a chapter title like `Vol.3 Ch.12.5`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Episode {
+    pub volume: Option<f64>,
+    pub chapter: Option<f64>,
+    /// last chapter of `Chapter 10-11`
+    pub chapter_end: Option<f64>,
+    /// `Chapter 12 Part 2` or `Chapter 12-2`
+    pub part: Option<u32>,
+    pub season: Option<u32>,
+    pub kind: EpisodeKind,
+}
+
+struct Patterns {
+    volume: Regex,
+    season: Regex,
+    season_episode: Regex,
+    chapter: Regex,
+    hash: Regex,
+    korean: Regex,
+    cjk: Regex,
+    part: Regex,
+    number: Regex,
+    oneshot: Regex,
+    omake: Regex,
+    extra: Regex,
+}
+
+const NUM: &str = r"(\d+(?:\.\d+)?)";
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        let re = |s: String| Regex::new(&s).unwrap();
+        Patterns {
+            volume: re(format!(r"\b(?:volume|vol|v\.)\.?\s*{NUM}")),
+            season: re(r"\bseason\s*(\d+)".to_string()),
+            season_episode: re(r"\bs(\d+)\s*e(\d+(?:\.\d+)?)\b".to_string()),
+            chapter: re(format!(
+                // `5-10` is always a range, `5 - 10` only if nothing but brackets follows,
+                // otherwise `Chapter 5 - 10 Years Later` would be a range
+                r"\b(?:chapter|chap|ch|episode|ep|c\.)\.?\s*#?\s*{NUM}(?:[-~]{NUM}|\s*[-~]\s*{NUM}\s*(?:$|[\[\](),:|]))?"
+            )),
+            hash: re(format!(r"#\s*{NUM}")),
+            korean: re(format!(r"제?\s*{NUM}\s*화")),
+            cjk: re(
+                r"第\s*(\d+(?:\.\d+)?|[零〇一二两三四五六七八九十百千]+)\s*[话話章回集]?"
+                    .to_string(),
+            ),
+            part: re(r"\b(?:part|pt)\.?\s*(\d+)".to_string()),
+            number: re(format!(r"^{NUM}$")),
+            oneshot: re(r"\bone[- ]?shot\b|読切|단편".to_string()),
+            omake: re(r"\bomake\b|おまけ".to_string()),
+            extra: re(
+                r"\b(?:extras?|specials?|bonus|prologue|epilogue|side stor(?:y|ies))\b|番外|특별"
+                    .to_string(),
+            ),
+        }
+    })
+}
+
+impl Episode {
+    pub fn parse(title: &str) -> Self {
+        let s = normalize(title);
+        let p = patterns();
+        let mut res = Episode {
+            kind: kind(&s),
+            ..Default::default()
+        };
+        res.volume = p.volume.captures(&s).and_then(|c| c[1].parse().ok());
+        res.season = p.season.captures(&s).and_then(|c| c[1].parse().ok());
+        if let Some(c) = p.season_episode.captures(&s) {
+            res.season = c[1].parse().ok();
+            res.chapter = c[2].parse().ok();
+        } else if let Some(c) = p.chapter.captures(&s) {
+            res.chapter = c[1].parse().ok();
+            if let (Some(start), Some(end)) = (res.chapter, c.get(2).or(c.get(3))) {
+                let end: f64 = end.as_str().parse().unwrap_or(0.0);
+                // `12-2` is the second part of chapter 12, not a range
+                if end > start {
+                    res.chapter_end = Some(end);
+                } else if end.fract() == 0.0 {
+                    res.part = Some(end as u32);
+                }
+            }
+        } else if let Some(c) = p.hash.captures(&s) {
+            res.chapter = c[1].parse().ok();
+        } else if let Some(c) = p.korean.captures(&s) {
+            res.chapter = c[1].parse().ok();
+        } else if let Some(c) = p.cjk.captures(&s) {
+            res.chapter = c[1].parse().ok().or_else(|| cjk_number(&c[1]));
+        } else if let Some(c) = p.number.captures(s.trim()) {
+            res.chapter = c[1].parse().ok();
+        }
+        if res.part.is_none() {
+            res.part = p.part.captures(&s).and_then(|c| c[1].parse().ok());
+        }
+        res
+    }
+}
+
+/// lowercase with full width characters as ascii
+fn normalize(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '！'..='～' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '　' => ' ',
+            _ => c,
+        })
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// whole words, so `Extraordinary` isn't an extra
+fn kind(s: &str) -> EpisodeKind {
+    let p = patterns();
+    if p.oneshot.is_match(s) {
+        EpisodeKind::Oneshot
+    } else if p.omake.is_match(s) {
+        EpisodeKind::Omake
+    } else if p.extra.is_match(s) {
+        EpisodeKind::Extra
+    } else {
+        EpisodeKind::Regular
+    }
+}
+
+/// 十二 => 12, 一百零五 => 105
+fn cjk_number(s: &str) -> Option<f64> {
+    let mut total = 0;
+    let mut current = 0;
+    for c in s.chars() {
+        let digit = match c {
+            '零' | '〇' => 0,
+            '一' => 1,
+            '二' | '两' => 2,
+            '三' => 3,
+            '四' => 4,
+            '五' => 5,
+            '六' => 6,
+            '七' => 7,
+            '八' => 8,
+            '九' => 9,
+            _ => {
+                let unit = match c {
+                    '十' => 10,
+                    '百' => 100,
+                    '千' => 1000,
+                    _ => return None,
+                };
+                // 十二 starts without a digit
+                total += current.max(1) * unit;
+                current = 0;
+                continue;
+            }
+        };
+        current = current * 10 + digit;
+    }
+    Some((total + current) as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// title, volume, chapter, chapter_end, part, kind
+    type Case = (
+        &'static str,
+        Option<f64>,
+        Option<f64>,
+        Option<f64>,
+        Option<u32>,
+        EpisodeKind,
+    );
+
+    #[test]
+    fn parse_titles() {
+        use EpisodeKind::*;
+        let cases: &[Case] = &[
+            ("Chapter 12", None, Some(12.0), None, None, Regular),
+            ("Ch. 12.5", None, Some(12.5), None, None, Regular),
+            ("Vol.3 Ch.12.5", Some(3.0), Some(12.5), None, None, Regular),
+            (
+                "Volume 2 Chapter 7",
+                Some(2.0),
+                Some(7.0),
+                None,
+                None,
+                Regular,
+            ),
+            ("v.4 c.20", Some(4.0), Some(20.0), None, None, Regular),
+            ("Chapter 10-11", None, Some(10.0), Some(11.0), None, Regular),
+            (
+                "Chapter 10 - 11",
+                None,
+                Some(10.0),
+                Some(11.0),
+                None,
+                Regular,
+            ),
+            (
+                "Chapter 10 ~ 11 [Group]",
+                None,
+                Some(10.0),
+                Some(11.0),
+                None,
+                Regular,
+            ),
+            ("Chapter 12-2", None, Some(12.0), None, Some(2), Regular),
+            (
+                "Chapter 5 - 10 Years Later",
+                None,
+                Some(5.0),
+                None,
+                None,
+                Regular,
+            ),
+            ("Chapter 5 - 3 Swords", None, Some(5.0), None, None, Regular),
+            (
+                "Chapter 12 Part 2",
+                None,
+                Some(12.0),
+                None,
+                Some(2),
+                Regular,
+            ),
+            ("Episode 4", None, Some(4.0), None, None, Regular),
+            ("#45", None, Some(45.0), None, None, Regular),
+            ("42", None, Some(42.0), None, None, Regular),
+            ("Ｃｈａｐｔｅｒ １２", None, Some(12.0), None, None, Regular),
+            ("第12话", None, Some(12.0), None, None, Regular),
+            ("第十二話", None, Some(12.0), None, None, Regular),
+            ("第一百零五章", None, Some(105.0), None, None, Regular),
+            ("제 15화", None, Some(15.0), None, None, Regular),
+            (
+                "Chapter 30: Extraordinary Day",
+                None,
+                Some(30.0),
+                None,
+                None,
+                Regular,
+            ),
+            ("Chapter 30.5: Extra", None, Some(30.5), None, None, Extra),
+            ("Special Chapter", None, None, None, None, Extra),
+            ("Side Story 3", None, None, None, None, Extra),
+            ("Vol.2 Omake", Some(2.0), None, None, None, Omake),
+            ("Oneshot", None, None, None, None, Oneshot),
+            ("One-Shot", None, None, None, None, Oneshot),
+            ("Bonuses and more", None, None, None, None, Regular),
+        ];
+        for (title, volume, chapter, chapter_end, part, kind) in cases {
+            let res = Episode::parse(title);
+            assert_eq!(
+                (res.volume, res.chapter, res.chapter_end, res.part, res.kind),
+                (*volume, *chapter, *chapter_end, *part, *kind),
+                "{title}"
+            );
+        }
+    }
+
+    #[test]
+    fn season_episode() {
+        let res = Episode::parse("S2E05");
+        assert_eq!((res.season, res.chapter), (Some(2), Some(5.0)));
+        let res = Episode::parse("Season 3 Episode 1");
+        assert_eq!((res.season, res.chapter), (Some(3), Some(1.0)));
+    }
+
+    #[test]
+    fn cjk_numbers() {
+        for (s, n) in [
+            ("十", 10.0),
+            ("十二", 12.0),
+            ("二十", 20.0),
+            ("一百零五", 105.0),
+        ] {
+            assert_eq!(cjk_number(s), Some(n), "{s}");
+        }
+        assert_eq!(cjk_number("abc"), None);
+    }
+}
@@ -0,0 +1,261 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Item of a rss 2.0 or atom feed
+#[derive(Debug, Clone, Default)]
+pub struct FeedItem {
+    pub title: String,
+    pub link: Option<String>,
+    pub published: Option<String>,
+    pub id: Option<String>,
+}
+
+struct Patterns {
+    item: Regex,
+    entry: Regex,
+    link: Regex,
+    cdata: Regex,
+    entity: Regex,
+    html: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        item: Regex::new(r"(?s)<item[\s>].*?</item>").unwrap(),
+        entry: Regex::new(r"(?s)<entry[\s>].*?</entry>").unwrap(),
+        link: Regex::new(r"<link\s[^>]*>").unwrap(),
+        cdata: Regex::new(r"(?s)<!\[CDATA\[(.*?)\]\]>").unwrap(),
+        entity: Regex::new(r"&(#x[0-9a-fA-F]+|#\d+|amp|lt|gt|quot|apos);").unwrap(),
+        html: Regex::new(r"<[^>]*>").unwrap(),
+    })
+}
+
+/// Only the fields needed for chapters are read, so a regex is enough
+pub fn parse_feed(xml: &str) -> Vec<FeedItem> {
+    let p = patterns();
+    let items = p.item.find_iter(xml).map(|m| {
+        let s = m.as_str();
+        FeedItem {
+            title: tag(s, "title").unwrap_or_default(),
+            link: tag(s, "link"),
+            published: tag(s, "pubDate").or_else(|| tag(s, "dc:date")),
+            id: tag(s, "guid"),
+        }
+    });
+    let entries = p.entry.find_iter(xml).map(|m| {
+        let s = m.as_str();
+        FeedItem {
+            title: tag(s, "title").unwrap_or_default(),
+            link: atom_link(s),
+            published: tag(s, "published").or_else(|| tag(s, "updated")),
+            id: tag(s, "id"),
+        }
+    });
+    items.chain(entries).collect()
+}
+
+/// Text of the first `<name>` element, markup of `type="html"` elements is removed
+fn tag(s: &str, name: &str) -> Option<String> {
+    let start = s.find(&format!("<{name}"))?;
+    let rest = &s[start + name.len() + 1..];
+    // `<title>` and `<title type="html">`, but not `<titles>`
+    if !rest.starts_with(['>', ' ', '\t', '\n', '\r']) {
+        return None;
+    }
+    let open_end = rest.find('>')?;
+    if rest[..open_end].ends_with('/') {
+        return None;
+    }
+    let html = matches!(
+        attr(&rest[..open_end], "type").as_deref(),
+        Some("html" | "xhtml")
+    );
+    let rest = &rest[open_end + 1..];
+    let end = rest.find(&format!("</{name}>"))?;
+    let mut text = decode(&rest[..end]);
+    if html {
+        text = decode(&patterns().html.replace_all(&text, ""));
+    }
+    match text.is_empty() {
+        true => None,
+        false => Some(text),
+    }
+}
+
+/// `<link rel="alternate" href="..."/>`, links without rel are alternate links
+fn atom_link(s: &str) -> Option<String> {
+    let mut fallback = None;
+    for m in patterns().link.find_iter(s) {
+        let link = m.as_str();
+        match attr(link, "rel").as_deref() {
+            None | Some("alternate") => return attr(link, "href"),
+            _ => fallback = fallback.or_else(|| attr(link, "href")),
+        }
+    }
+    fallback
+}
+
+/// Value of a `name="..."` attribute
+fn attr(element: &str, name: &str) -> Option<String> {
+    let pattern = format!("{name}=\"");
+    let (start, _) = element
+        .match_indices(&pattern)
+        .find(|(i, _)| element[..*i].ends_with(char::is_whitespace))?;
+    let value = &element[start + pattern.len()..];
+    Some(decode(&value[..value.find('"')?]))
+}
+
+/// Resolves entities, the content of CDATA sections is kept as it is
+fn decode(s: &str) -> String {
+    let p = patterns();
+    let mut res = String::new();
+    let mut last = 0;
+    for c in p.cdata.captures_iter(s) {
+        let m = c.get(0).unwrap();
+        res.push_str(&entities(&s[last..m.start()]));
+        res.push_str(&c[1]);
+        last = m.end();
+    }
+    res.push_str(&entities(&s[last..]));
+    res.trim().to_string()
+}
+
+fn entities(s: &str) -> String {
+    patterns()
+        .entity
+        .replace_all(s, |c: &regex::Captures| match &c[1] {
+            "amp" => "&".to_string(),
+            "lt" => "<".to_string(),
+            "gt" => ">".to_string(),
+            "quot" => "\"".to_string(),
+            "apos" => "'".to_string(),
+            v => {
+                let code = match v.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => v[1..].parse().ok(),
+                };
+                code.and_then(char::from_u32)
+                    .map(|c| c.to_string())
+                    .unwrap_or_default()
+            }
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel>
+  <title>Some Scans</title>
+  <link>https://example.com</link>
+  <item>
+    <title><![CDATA[Chapter 12 &amp; <b>13</b>]]></title>
+    <link>https://example.com/read?ch=12&amp;lang=en</link>
+    <pubDate>Mon, 02 Jan 2023 10:00:00 +0000</pubDate>
+    <guid isPermaLink="false">ch-12</guid>
+  </item>
+  <item>
+    <title>Chapter 11 &#8211; Caf&#xE9;</title>
+    <link>https://example.com/read?ch=11</link>
+    <dc:date>2023-01-01</dc:date>
+  </item>
+</channel></rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Some Scans</title>
+  <entry>
+    <title type="html">&lt;em&gt;Chapter 5&lt;/em&gt; &amp;amp; Extra</title>
+    <link rel="edit" href="https://example.com/edit/5"/>
+    <link rel="alternate" type="text/html"
+      href="https://example.com/ch/5"/>
+    <id>urn:ch:5</id>
+    <updated>2023-02-01T00:00:00Z</updated>
+  </entry>
+  <entry>
+    <title>Chapter 4</title>
+    <link rel="enclosure" href="https://example.com/ch/4.zip"/>
+    <published>2023-01-01T00:00:00Z</published>
+    <updated>2023-01-05T00:00:00Z</updated>
+  </entry>
+  <entry>
+    <title/>
+    <link href="https://example.com/ch/3"/>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn rss_items() {
+        let items = parse_feed(RSS);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "Chapter 12 &amp; <b>13</b>");
+        assert_eq!(
+            items[0].link.as_deref(),
+            Some("https://example.com/read?ch=12&lang=en")
+        );
+        assert_eq!(
+            items[0].published.as_deref(),
+            Some("Mon, 02 Jan 2023 10:00:00 +0000")
+        );
+        assert_eq!(items[0].id.as_deref(), Some("ch-12"));
+        assert_eq!(items[1].title, "Chapter 11 \u{2013} Caf\u{e9}");
+        assert_eq!(items[1].published.as_deref(), Some("2023-01-01"));
+        assert_eq!(items[1].id, None);
+    }
+
+    #[test]
+    fn atom_entries() {
+        let items = parse_feed(ATOM);
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].title, "Chapter 5 & Extra");
+        assert_eq!(items[0].link.as_deref(), Some("https://example.com/ch/5"));
+        assert_eq!(items[0].id.as_deref(), Some("urn:ch:5"));
+        assert_eq!(items[0].published.as_deref(), Some("2023-02-01T00:00:00Z"));
+        assert_eq!(
+            items[1].link.as_deref(),
+            Some("https://example.com/ch/4.zip")
+        );
+        assert_eq!(items[1].published.as_deref(), Some("2023-01-01T00:00:00Z"));
+        assert_eq!(items[2].title, "");
+        assert_eq!(items[2].link.as_deref(), Some("https://example.com/ch/3"));
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(tag("<titles>a</titles>", "title"), None);
+        assert_eq!(tag("<title/>", "title"), None);
+        assert_eq!(tag("<title>  </title>", "title"), None);
+        assert_eq!(tag("<title\n>a</title>", "title").as_deref(), Some("a"));
+        assert_eq!(
+            tag(r#"<title type="text">&lt;b&gt;</title>"#, "title").as_deref(),
+            Some("<b>")
+        );
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(atom_link("<link>https://a</link>"), None);
+        assert_eq!(
+            atom_link(r#"<link rel="self" href="https://a"/><link href="https://b"/>"#).as_deref(),
+            Some("https://b")
+        );
+        assert_eq!(
+            atom_link(
+                r#"<link rel="self" href="https://a"/><link rel="replies" href="https://b"/>"#
+            )
+            .as_deref(),
+            Some("https://a")
+        );
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(decode("a &amp; b &lt;&gt; &quot;&apos;"), "a & b <> \"'");
+        assert_eq!(decode("&#65;&#x42;&#xZZ; &unknown;"), "AB&#xZZ; &unknown;");
+        assert_eq!(decode("&#1114112;"), "");
+        assert_eq!(decode(" <![CDATA[ &amp; ]]> &amp; "), "&amp;  &");
+    }
+}
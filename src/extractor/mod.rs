@@ -1,7 +1,9 @@
 use crate::downloader::download;
+use crate::pages::tags::translate_tags_to;
 use crate::services::config_to_request_builder;
+use crate::services::search::SearchPage;
 use crate::ScrapeError;
-use api_structure::scraper::ScrapeSearchResult;
+use api_structure::scraper::{ExternalSearchData, ScrapeSearchResult, SimpleSearch, ValidSearch};
 use reqwest::Client;
 use scraper::{Html, Selector};
 use serde::Deserialize;
@@ -9,6 +11,9 @@ use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::path::Path;
 
+pub mod date;
+pub mod episode;
+pub mod feed;
 pub mod parser;
 
 #[derive(Deserialize)]
@@ -23,6 +28,22 @@ pub struct SearchServiceDeserialized {
     status: Option<String>,
     cover: String,
     offset: Option<u32>,
+    /// element that only exists if there is a next page
+    next_page: Option<String>,
+    /// name => value of `{sort}`
+    #[serde(default)]
+    sorts: HashMap<String, String>,
+    /// name => value of `{status}`
+    #[serde(default)]
+    statuses: HashMap<String, String>,
+    /// name => value, the selected values are joined into `{tags}`
+    #[serde(default)]
+    tags: HashMap<String, String>,
+    /// defaults to `,`
+    tag_separator: Option<String>,
+    /// values of `{order}`, default to `asc` and `desc`
+    order_asc: Option<String>,
+    order_desc: Option<String>,
 }
 
 impl SearchServiceDeserialized {
@@ -44,6 +65,15 @@ impl SearchServiceDeserialized {
             type_: self.type_.map(|v| Selector::parse(&v).unwrap()),
             status: self.status.map(|v| Selector::parse(&v).unwrap()),
             offset: self.offset,
+            next_page: self.next_page.map(|v| Selector::parse(&v).unwrap()),
+            sorts: self.sorts,
+            statuses: self.statuses,
+            tags: self.tags,
+            tag_separator: self.tag_separator.unwrap_or(",".to_string()),
+            order: (
+                self.order_asc.unwrap_or("asc".to_string()),
+                self.order_desc.unwrap_or("desc".to_string()),
+            ),
         }
     }
 }
@@ -58,15 +88,79 @@ pub struct SearchServiceScrapeData {
     type_: Option<Selector>,
     status: Option<Selector>,
     offset: Option<u32>,
+    next_page: Option<Selector>,
+    sorts: HashMap<String, String>,
+    statuses: HashMap<String, String>,
+    tags: HashMap<String, String>,
+    tag_separator: String,
+    /// (asc, desc)
+    order: (String, String),
 }
 
 impl SearchServiceScrapeData {
+    /// None if the file doesn't declare any filters
+    pub fn valid(&self) -> Option<ValidSearch> {
+        if self.sorts.is_empty() && self.statuses.is_empty() && self.tags.is_empty() {
+            return None;
+        }
+        let sorted = |map: &HashMap<String, String>| {
+            let mut keys = map.keys().cloned().collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+        Some(ValidSearch {
+            sorts: sorted(&self.sorts),
+            tags: sorted(&self.tags),
+            status: sorted(&self.statuses),
+        })
+    }
+
     pub async fn search(
         &self,
         client: &Client,
-        query: String,
-        page: u32,
-    ) -> Result<Vec<ScrapeSearchResult>, ScrapeError> {
+        search: ExternalSearchData,
+    ) -> Result<SearchPage, ScrapeError> {
+        let mut search = match search {
+            ExternalSearchData::Simple(v) => v,
+            ExternalSearchData::String((search, page)) => SimpleSearch {
+                search,
+                sort: None,
+                desc: false,
+                status: None,
+                tags: vec![],
+                page,
+            },
+        };
+        let vocabulary = self.tags.keys().map(|v| v.as_str()).collect::<Vec<_>>();
+        search.tags = translate_tags_to(&search.tags, &vocabulary);
+        if let Some(valid) = self.valid() {
+            if !search.validate(&valid) {
+                return Err(ScrapeError::input_error("couldnt match ValidSearch"));
+            }
+        }
+        let lookup = |map: &HashMap<String, String>, key: &str| {
+            map.get(key)
+                .cloned()
+                .ok_or(ScrapeError::input_error(format!("unknown filter: {key}")))
+        };
+        let sort = match &search.sort {
+            Some(v) => lookup(&self.sorts, v)?,
+            None => String::new(),
+        };
+        let status = match &search.status {
+            Some(v) => lookup(&self.statuses, v)?,
+            None => String::new(),
+        };
+        let tags = search
+            .tags
+            .iter()
+            .map(|v| lookup(&self.tags, v))
+            .collect::<Result<Vec<_>, _>>()?;
+        let order = match search.desc {
+            true => &self.order.1,
+            false => &self.order.0,
+        };
+        let (query, page) = (search.search, search.page);
         let mut url = None;
         if query.is_empty() {
             if let Some(u) = &self.url_empty {
@@ -83,6 +177,17 @@ impl SearchServiceScrapeData {
             .replace(
                 "{offset}",
                 &((page - 1) * self.offset.unwrap_or(0)).to_string(),
+            )
+            .replace("{sort}", &urlencoding::encode(&sort))
+            .replace("{order}", &urlencoding::encode(order))
+            .replace("{status}", &urlencoding::encode(&status))
+            .replace(
+                "{tags}",
+                &tags
+                    .iter()
+                    .map(|v| urlencoding::encode(v))
+                    .collect::<Vec<_>>()
+                    .join(&self.tag_separator),
             );
         let html = download(config_to_request_builder(client, &self.headers, &url)).await?;
         let doc = Html::parse_document(html.as_str());
@@ -143,6 +248,18 @@ impl SearchServiceScrapeData {
                 status: status.as_ref().map(|v| v.get(i).unwrap().to_string()),
             })
         }
-        Ok(res)
+        // without a next page selector a full page is assumed to have a successor
+        let has_next = match (&self.next_page, self.offset) {
+            (Some(next), _) => doc.select(next).next().is_some(),
+            (None, Some(offset)) => res.len() as u32 >= offset,
+            (None, None) => !res.is_empty(),
+        };
+        Ok(SearchPage {
+            results: res,
+            page,
+            has_next,
+            total: None,
+            external_ids: Default::default(),
+        })
     }
 }
@@ -6,9 +6,23 @@ mod services;
 //mod tests;
 
 pub use error::ScrapeError;
+pub use extractor::episode::{Episode, EpisodeKind};
+pub use pages::mangadex::MangaDexOptions;
+pub use pages::mangaupdates::query::{parse_query, QueryError};
+pub use pages::mangaupdates::search as mangaupdates_offline;
+pub use pages::tags::{
+    canonical, normalize_tags, translate_tag, translate_tags, translate_tags_to, CanonicalTag,
+    TagSource,
+};
 pub use services::icon::ExternalSite;
 pub use services::init;
-pub use services::metadata::MetaDataService;
-pub use services::multisite::MultiSiteService;
-pub use services::search::SearchService;
+pub use services::metadata::merge::{MergedMetadata, Sourced};
+pub use services::metadata::model::{
+    MangaMetadata, Person, Publisher, Relation, Role, Status, Title,
+};
+pub use services::metadata::{ItemOrArray, MetaDataService};
+pub use services::multisite::{ChapterList, DuplicatePolicy, Info, MultiSiteService};
+pub use services::scraper::{Scraper, ScraperBuilder};
+pub use services::search::{AggregatedSearch, SearchGroup, SearchPage, SearchService};
 pub use services::singlesite::SingleSiteService;
+pub use services::tracker::{ChapterDiff, UpdateTracker};
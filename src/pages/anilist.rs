@@ -1,5 +1,7 @@
 use crate::downloader::download;
-use crate::services::metadata::ItemOrArray;
+use crate::pages::tags::{translate_tags, TagSource};
+use crate::services::metadata::model::{MangaMetadata, Publisher, Relation, Status, Title};
+use crate::services::search::SearchPage;
 use crate::ScrapeError;
 use api_structure::scraper::ValidSearch;
 use api_structure::scraper::{ScrapeSearchResult, SimpleSearch};
@@ -11,6 +13,8 @@ use std::collections::HashMap;
 const QUERY: &str = "
 query ($id: Int) { # Define which variables will be used in the query (id)
   Media (id: $id, type: MANGA) { # Insert our variables into the query arguments (id) (type: ANIME is hard-coded in the query)
+    id
+    idMal
     title {
       romaji
       english
@@ -40,6 +44,12 @@ query ($id: Int) { # Define which variables will be used in the query (id)
     isAdult
     countryOfOrigin
     isLicensed
+    averageScore
+    rankings {
+      rank
+      type
+      allTime
+    }
     tags {
       name
     }
@@ -50,9 +60,21 @@ query ($id: Int) { # Define which variables will be used in the query (id)
         }
       }
     }
+    staff {
+      edges {
+        role
+        node {
+          id
+          name {
+            full
+          }
+        }
+      }
+    }
     relations {
       edges {
         relationType(version:2)node {
+          id
           title{
             userPreferred
           }
@@ -67,10 +89,7 @@ query ($id: Int) { # Define which variables will be used in the query (id)
 }
 ";
 
-pub async fn get_data(
-    client: &Client,
-    url: &str,
-) -> Result<HashMap<String, ItemOrArray>, ScrapeError> {
+pub async fn get_data(client: &Client, url: &str) -> Result<MangaMetadata, ScrapeError> {
     let mut id = url
         .split_once("anilist.co/manga/")
         .ok_or(ScrapeError::input_error("Invalid url"))?
@@ -87,50 +106,108 @@ pub async fn get_data(
     )
     .await?;
     let parsed: InfoResponse = serde_json::from_str(&resp)?;
-    let mut media = parsed.data.media;
-    let mut hm = HashMap::new();
-    hm.insert("cover", ItemOrArray::Item(media.cover_image.extra_large));
-    if let Some(banner_image) = media.banner_image {
-        hm.insert("banner", ItemOrArray::Item(banner_image));
+    let media = parsed.data.media;
+    let mut res = MangaMetadata {
+        cover: Some(media.cover_image.extra_large),
+        banner: media.banner_image,
+        description: Some(media.description),
+        tags: media.tags.into_iter().map(|v| v.name).collect(),
+        genres: media.genres,
+        status: Status::parse(&media.status),
+        kind: Some(media.format),
+        start_date: media.start_date.display(),
+        end_date: media.end_date.display(),
+        year: media.start_date.year.map(|v| v as i32),
+        ..Default::default()
+    };
+    let native = native_language(&media.country_of_origin);
+    if let Some(native_title) = media.title.native {
+        res.titles.push(Title::new(native_title, Some(native)));
+    }
+    res.titles.push(Title::new(
+        media.title.romaji,
+        Some(format!("{native}-ro").as_str()),
+    ));
+    if let Some(english) = media.title.english {
+        res.titles.push(Title::new(english, Some("en")));
     }
-    let mut titles = vec![
-        format!("Native: {}", media.title.native),
-        format!("Rative: {}", media.title.romaji),
-        format!("English: {}", media.title.english),
-    ];
     for synonym in media.synonyms {
-        titles.push(format!("Unknown: {}", synonym));
+        res.titles.push(Title::new(synonym, None));
     }
-    hm.insert("titles", ItemOrArray::Array(titles));
-    let mut tags: Vec<String> = media.tags.into_iter().map(|v| v.name).collect();
-    tags.append(&mut media.genres);
-    hm.insert("tags", ItemOrArray::Array(tags));
-    hm.insert("licensed", ItemOrArray::Item(media.is_licensed.to_string()));
-    hm.insert("adult", ItemOrArray::Item(media.is_adult.to_string()));
-    hm.insert(
-        "country_of_origin",
-        ItemOrArray::Item(media.country_of_origin),
-    );
-    hm.insert("description", ItemOrArray::Item(media.description));
-    if let Some(start_date) = media.start_date.display() {
-        hm.insert("start_date", ItemOrArray::Item(start_date));
+    for edge in media.staff.edges {
+        res.add_person(
+            edge.node.name.full,
+            &edge.role,
+            Some(edge.node.id.to_string()),
+        );
     }
-    if let Some(end_date) = media.end_date.display() {
-        hm.insert("end_date", ItemOrArray::Item(end_date));
+    for edge in media.studios.edges {
+        if let Some(name) = edge.pointer("/node/name").and_then(|v| v.as_str()) {
+            res.publishers.push(Publisher {
+                name: name.to_string(),
+                kind: Some("studio".to_string()),
+            })
+        }
     }
-    hm.insert("format", ItemOrArray::Item(media.format));
-    hm.insert("status", ItemOrArray::Item(media.status));
-    hm.insert("source", ItemOrArray::Item(media.source));
-    hm.insert("type", ItemOrArray::Item(media.r#type));
-    let mut relations = vec![];
     for relation in media.relations.edges {
-        relations.push(format!(
-            "{} {}: {}",
-            relation.relation_type, relation.node.r#type, relation.node.title.user_preferred,
-        ));
+        res.relations.push(Relation {
+            kind: relation.relation_type,
+            title: Some(relation.node.title.user_preferred),
+            target: Some(("anilist".to_string(), relation.node.id.to_string())),
+            media_type: Some(relation.node.r#type),
+        });
     }
-    hm.insert("relations", ItemOrArray::Array(relations));
-    Ok(hm.into_iter().map(|v| (v.0.to_string(), v.1)).collect())
+    res.rating = media.average_score.map(|v| v as f64 / 10.0);
+    for ranking in media.rankings.iter().filter(|v| v.all_time) {
+        match ranking.r#type.as_str() {
+            "RATED" => res.rank = Some(ranking.rank),
+            "POPULAR" => res.popularity = Some(ranking.rank),
+            _ => {}
+        }
+    }
+    res.external_ids
+        .insert("anilist".to_string(), media.id.to_string());
+    if let Some(id_mal) = media.id_mal {
+        res.external_ids
+            .insert("myanimelist".to_string(), id_mal.to_string());
+    }
+    res.insert_extra("licensed", media.is_licensed);
+    res.insert_extra("adult", media.is_adult);
+    res.insert_extra("country_of_origin", media.country_of_origin);
+    res.insert_extra("source", media.source);
+    res.insert_extra("type", media.r#type);
+    Ok(res)
+}
+
+fn native_language(country: &str) -> &str {
+    match country {
+        "KR" => "ko",
+        "CN" => "zh",
+        "TW" => "zh-tw",
+        _ => "ja",
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StaffName {
+    pub full: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StaffNode {
+    pub id: i64,
+    pub name: StaffName,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StaffEdge {
+    pub role: String,
+    pub node: StaffNode,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Staff {
+    pub edges: Vec<StaffEdge>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -141,6 +218,7 @@ struct Title1 {
 
 #[derive(Serialize, Deserialize)]
 struct Node {
+    pub id: i64,
     pub title: Title1,
     #[serde(rename = "type")]
     pub r#type: String,
@@ -149,7 +227,7 @@ struct Node {
 }
 
 #[derive(Serialize, Deserialize)]
-struct Relation {
+struct RelationEdge {
     #[serde(rename = "relationType")]
     pub relation_type: String,
     pub node: Node,
@@ -157,7 +235,7 @@ struct Relation {
 
 #[derive(Serialize, Deserialize)]
 struct Relations {
-    pub edges: Vec<Relation>,
+    pub edges: Vec<RelationEdge>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -165,6 +243,15 @@ struct Studios {
     pub edges: Vec<Value>,
 }
 
+#[derive(Serialize, Deserialize)]
+struct Ranking {
+    pub rank: u64,
+    #[serde(rename = "type")]
+    pub r#type: String,
+    #[serde(rename = "allTime")]
+    pub all_time: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 struct Tag {
     pub name: String,
@@ -172,7 +259,10 @@ struct Tag {
 
 #[derive(Serialize, Deserialize)]
 struct Media {
-    pub title: Title,
+    pub id: i64,
+    #[serde(rename = "idMal")]
+    pub id_mal: Option<i64>,
+    pub title: AnilistTitle,
     #[serde(rename = "coverImage")]
     pub cover_image: CoverImage,
     #[serde(rename = "bannerImage")]
@@ -195,8 +285,13 @@ struct Media {
     pub country_of_origin: String,
     #[serde(rename = "isLicensed")]
     pub is_licensed: bool,
+    #[serde(rename = "averageScore")]
+    pub average_score: Option<i64>,
+    #[serde(default)]
+    pub rankings: Vec<Ranking>,
     pub tags: Vec<Tag>,
     pub studios: Studios,
+    pub staff: Staff,
     pub relations: Relations,
 }
 
@@ -211,10 +306,10 @@ struct Data {
 }
 
 #[derive(Serialize, Deserialize)]
-struct Title {
+struct AnilistTitle {
     pub romaji: String,
-    pub english: String,
-    pub native: String,
+    pub english: Option<String>,
+    pub native: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -337,6 +432,29 @@ const QUERY2: &str = "query (
 }
 ";
 
+/// anilist filters these with `genre_in` instead of `tag_in`
+const GENRES: [&str; 19] = [
+    "Action",
+    "Adventure",
+    "Comedy",
+    "Drama",
+    "Ecchi",
+    "Fantasy",
+    "Hentai",
+    "Horror",
+    "Mahou Shoujo",
+    "Mecha",
+    "Music",
+    "Mystery",
+    "Psychological",
+    "Romance",
+    "Sci-Fi",
+    "Slice of Life",
+    "Sports",
+    "Supernatural",
+    "Thriller",
+];
+
 fn get_sort(s: &str, desc: bool) -> Value {
     let desc = match desc {
         true => "DESC",
@@ -365,10 +483,8 @@ fn get_status(s: &str) -> &str {
         _ => unreachable!(),
     }
 }
-pub async fn search(
-    client: &Client,
-    search: &SimpleSearch,
-) -> Result<Vec<ScrapeSearchResult>, ScrapeError> {
+pub async fn search(client: &Client, mut search: SimpleSearch) -> Result<SearchPage, ScrapeError> {
+    search.tags = translate_tags(&search.tags, TagSource::Anilist);
     let valid: ValidSearch = ValidSearch::anilist();
     if !search.validate(&valid) {
         return Err(ScrapeError::input_error("couldnt match ValidSearch"));
@@ -398,8 +514,15 @@ pub async fn search(
     if let Some(status) = &search.status {
         items.insert("status", serde_json::to_value(get_status(status)).unwrap());
     }
-    if !search.tags.is_empty() {
-        items.insert("tags", serde_json::to_value(&search.tags).unwrap());
+    let (genres, tags): (Vec<_>, Vec<_>) = search
+        .tags
+        .iter()
+        .partition(|v| GENRES.contains(&v.as_str()));
+    if !genres.is_empty() {
+        items.insert("genres", serde_json::to_value(&genres).unwrap());
+    }
+    if !tags.is_empty() {
+        items.insert("tags", serde_json::to_value(&tags).unwrap());
     }
     let json = json!({"query": QUERY2, "variables": items });
 
@@ -411,17 +534,24 @@ pub async fn search(
     )
     .await?;
     let data: SearchResponse = serde_json::from_str(&resp)?;
-    let data = data.data.page.media;
-    Ok(data
-        .into_iter()
-        .map(|v| ScrapeSearchResult {
-            title: v.title.user_preferred,
-            url: format!("https://anilist.co/manga/{}", v.id),
-            cover: v.cover_image.extra_large,
-            r#type: Some(v.r#type),
-            status: Some(v.status),
-        })
-        .collect())
+    let page = data.data.page;
+    Ok(SearchPage {
+        results: page
+            .media
+            .into_iter()
+            .map(|v| ScrapeSearchResult {
+                title: v.title.user_preferred,
+                url: format!("https://anilist.co/manga/{}", v.id),
+                cover: v.cover_image.extra_large,
+                r#type: Some(v.r#type),
+                status: Some(v.status),
+            })
+            .collect(),
+        page: page.page_info.current_page as u32,
+        has_next: page.page_info.has_next_page,
+        total: Some(page.page_info.total as u64),
+        external_ids: Default::default(),
+    })
 }
 
 #[derive(Serialize, Deserialize)]
@@ -470,6 +600,8 @@ struct PageInfo {
 
 #[derive(Serialize, Deserialize)]
 struct Page {
+    #[serde(rename = "pageInfo")]
+    pub page_info: PageInfo,
     pub media: Vec<Struct>,
 }
 
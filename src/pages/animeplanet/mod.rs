@@ -1,6 +1,7 @@
-use crate::downloader::download;
+use crate::downloader::{download, UA_ERR};
 use crate::pages::animeplanet::tags::TAGS;
-use crate::pages::hidden::pages::mangadex::UA_ERR;
+use crate::pages::tags::{translate_tags, TagSource};
+use crate::services::search::SearchPage;
 use crate::ScrapeError;
 use api_structure::scraper::{ScrapeSearchResult, SimpleSearch, ValidSearch};
 use reqwest::header::USER_AGENT;
@@ -53,8 +54,9 @@ fn get_status(s: &str) -> &str {
 
 pub async fn search(
     client: &Client,
-    search_request: SimpleSearch,
-) -> Result<Vec<ScrapeSearchResult>, ScrapeError> {
+    mut search_request: SimpleSearch,
+) -> Result<SearchPage, ScrapeError> {
+    search_request.tags = translate_tags(&search_request.tags, TagSource::AnimePlanet);
     let valid: ValidSearch = get_valid();
     if !search_request.validate(&valid) {
         return Err(ScrapeError::input_error("couldnt match ValidSearch"));
@@ -81,7 +83,11 @@ pub async fn search(
         let mut tag_ids = vec![];
         let tags = TAGS.clone().into_iter().collect::<HashMap<_, _>>();
         for tag in &search_request.tags {
-            tag_ids.push(*tags.get(tag.as_str()).unwrap());
+            tag_ids.push(
+                *tags
+                    .get(tag.as_str())
+                    .ok_or(ScrapeError::input_error(format!("unknown tag: {tag}")))?,
+            );
         }
         items.push(format!(
             "include_tags={}",
@@ -129,6 +135,12 @@ pub async fn search(
             status: None,
         });
     }
-
-    Ok(res)
+    let next = Selector::parse(".pagination .next a").unwrap();
+    Ok(SearchPage {
+        results: res,
+        page: search_request.page,
+        has_next: doc.select(&next).next().is_some(),
+        total: None,
+        external_ids: Default::default(),
+    })
 }
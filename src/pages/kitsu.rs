@@ -1,15 +1,14 @@
 use crate::downloader::download;
-use crate::services::metadata::ItemOrArray;
+use crate::pages::tags::{translate_tags, TagSource};
+use crate::services::metadata::model::{MangaMetadata, Publisher, Status, Title};
+use crate::services::search::SearchPage;
 use crate::ScrapeError;
 use api_structure::scraper::{ScrapeSearchResult, SimpleSearch};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
-pub async fn get_data(
-    client: &Client,
-    url: &str,
-) -> Result<HashMap<String, ItemOrArray>, ScrapeError> {
+pub async fn get_data(client: &Client, url: &str) -> Result<MangaMetadata, ScrapeError> {
     let mut slug = url
         .split_once("kitsu.io/manga/")
         .map(|v| v.1)
@@ -20,54 +19,69 @@ pub async fn get_data(
     let url = format!("https://kitsu.io/api/edge/manga?fields%5Bcategories%5D=slug%2Ctitle&filter%5Bslug%5D={slug}&include=categories,genres");
     let text = download(client.get(url)).await?;
     let mut parsed: MangaResponse = serde_json::from_str(&text)?;
+    if parsed.data.is_empty() {
+        return Err(ScrapeError::node_not_found());
+    }
     let data = parsed.data.remove(0);
-    let mut hm = HashMap::new();
-    let mut titles = vec![format!(
-        "canonical_title: {}",
-        data.attributes.canonical_title
-    )];
+    let mut res = MangaMetadata {
+        description: Some(data.attributes.description),
+        cover: Some(data.attributes.poster_image.original),
+        banner: Some(data.attributes.cover_image.original),
+        status: Status::parse(&data.attributes.status),
+        kind: Some(data.attributes.subtype),
+        year: data
+            .attributes
+            .start_date
+            .split('-')
+            .next()
+            .and_then(|v| v.parse().ok()),
+        start_date: Some(data.attributes.start_date),
+        tags: parsed
+            .included
+            .into_iter()
+            .map(|v| v.attributes.get_name())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    };
+    res.titles
+        .push(Title::new(data.attributes.canonical_title, None));
     for (typ, title) in data.attributes.titles {
-        titles.push(format!("{}: {}", typ, title));
+        res.titles
+            .push(Title::new(title, Some(title_language(&typ).as_str())));
     }
-
     for title in data.attributes.abbreviated_titles {
-        titles.push(format!("unknown: {}", title))
+        res.titles.push(Title::new(title, None))
+    }
+    if let Some(serialization) = data.attributes.serialization {
+        res.publishers.push(Publisher {
+            name: serialization,
+            kind: Some("serialization".to_string()),
+        });
+    }
+    res.external_ids.insert("kitsu".to_string(), data.id);
+    res.insert_extra("type", data.r#type);
+    res.insert_extra("age_rating", data.attributes.age_rating);
+    Ok(res)
+}
+
+/// kitsu uses `en_jp` for romanized japanese
+fn title_language(key: &str) -> String {
+    match key.split_once('_') {
+        Some(("en", lang)) if lang != "us" => format!("{}-ro", romanized_source(lang)),
+        Some((lang, _)) => lang.to_string(),
+        None => key.to_string(),
+    }
+}
+
+fn romanized_source(country: &str) -> &str {
+    match country {
+        "kr" => "ko",
+        "cn" => "zh",
+        "jp" => "ja",
+        v => v,
     }
-    hm.insert(
-        "description",
-        ItemOrArray::Item(data.attributes.description),
-    );
-    hm.insert("type", ItemOrArray::Item(data.r#type));
-    hm.insert("subtype", ItemOrArray::Item(data.attributes.subtype));
-    hm.insert(
-        "cover",
-        ItemOrArray::Item(data.attributes.cover_image.original),
-    );
-    hm.insert(
-        "poster",
-        ItemOrArray::Item(data.attributes.poster_image.original),
-    );
-    hm.insert("age_rating", ItemOrArray::Item(data.attributes.age_rating));
-    hm.insert("status", ItemOrArray::Item(data.attributes.status));
-    hm.insert("start_date", ItemOrArray::Item(data.attributes.start_date));
-    hm.insert(
-        "serialization",
-        ItemOrArray::Item(data.attributes.serialization),
-    );
-    hm.insert(
-        "tags",
-        ItemOrArray::Array(
-            parsed
-                .included
-                .into_iter()
-                .map(|v| v.attributes.get_name())
-                .collect::<HashSet<_>>()
-                .into_iter()
-                .collect(),
-        ),
-    );
-    hm.insert("titles", ItemOrArray::Array(titles));
-    Ok(hm.into_iter().map(|v| (v.0.to_string(), v.1)).collect())
 }
 
 #[derive(Serialize, Deserialize)]
@@ -78,6 +92,7 @@ struct MangaResponse {
 
 #[derive(Serialize, Deserialize)]
 struct Info {
+    pub id: String,
     #[serde(rename = "type")]
     pub r#type: String,
     pub attributes: MangaAttributes,
@@ -101,7 +116,7 @@ struct MangaAttributes {
     pub poster_image: Image,
     #[serde(rename = "coverImage")]
     pub cover_image: Image,
-    pub serialization: String,
+    pub serialization: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -133,10 +148,7 @@ impl Attributes1 {
     }
 }
 
-pub async fn search(
-    client: &Client,
-    search: SimpleSearch,
-) -> Result<Vec<ScrapeSearchResult>, ScrapeError> {
+pub async fn search(client: &Client, search: SimpleSearch) -> Result<SearchPage, ScrapeError> {
     let limit = 20;
     let offset = (search.page - 1) * limit;
     let mut url = format!("https://kitsu.io/api/edge/manga?fields%5Bmanga%5D=slug%2CcanonicalTitle%2Ctitles%2CposterImage%2Cdescription%2CaverageRating%2CstartDate%2CpopularityRank%2CratingRank&page%5Blimit%5D={limit}&page%5Boffset%5D={offset}");
@@ -145,7 +157,7 @@ pub async fn search(
         url = format!("{url}&filter%5Btext%5D={query}")
     }
     if !search.tags.is_empty() {
-        let categories = search.tags.join(",");
+        let categories = translate_tags(&search.tags, TagSource::Kitsu).join(",");
         url = format!("{url}&filter%5Bcategories%5D={categories}")
     }
     if let Some(sort) = search.sort {
@@ -153,17 +165,23 @@ pub async fn search(
     }
     let text = download(client.get(url)).await?;
     let data: SearchResponse = serde_json::from_str(&text)?;
-    let data = data.data;
-    Ok(data
-        .into_iter()
-        .map(|v| ScrapeSearchResult {
-            title: v.attributes.canonical_title,
-            url: format!("https://kitsu.io/manga/{}", v.attributes.slug),
-            cover: v.attributes.poster_image.original,
-            r#type: Some(v.r#type),
-            status: None,
-        })
-        .collect())
+    Ok(SearchPage {
+        results: data
+            .data
+            .into_iter()
+            .map(|v| ScrapeSearchResult {
+                title: v.attributes.canonical_title,
+                url: format!("https://kitsu.io/manga/{}", v.attributes.slug),
+                cover: v.attributes.poster_image.original,
+                r#type: Some(v.r#type),
+                status: None,
+            })
+            .collect(),
+        page: search.page,
+        has_next: data.links.next.is_some(),
+        total: data.meta.count,
+        external_ids: Default::default(),
+    })
 }
 
 fn get_sort(s: &str) -> &str {
@@ -213,4 +231,18 @@ struct Struct {
 #[derive(Serialize, Deserialize)]
 struct SearchResponse {
     pub data: Vec<Struct>,
+    #[serde(default)]
+    pub meta: SearchMeta,
+    #[serde(default)]
+    pub links: SearchLinks,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SearchMeta {
+    pub count: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct SearchLinks {
+    pub next: Option<String>,
 }
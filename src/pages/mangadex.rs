@@ -0,0 +1,589 @@
+use crate::downloader::download;
+use crate::extractor::date::{self, parse_date};
+use crate::extractor::episode::Episode;
+use crate::pages::tags::translate_tags_to;
+use crate::services::metadata::model::{MangaMetadata, Relation, Status, Title};
+use crate::services::multisite::Info;
+use crate::services::search::SearchPage;
+use crate::ScrapeError;
+use api_structure::scraper::{ScrapeSearchResult, SimpleSearch, ValidSearch};
+use reqwest::header::USER_AGENT;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::OnceCell;
+
+/// mangadex asks api clients to identify themselves instead of faking a browser
+const UA: &str = concat!("manread_scraper/", env!("CARGO_PKG_VERSION"));
+const API: &str = "https://api.mangadex.org";
+const FEED_LIMIT: u32 = 500;
+const SEARCH_LIMIT: u32 = 50;
+
+#[derive(Clone, Debug)]
+pub struct MangaDexOptions {
+    /// languages of the chapter feed
+    pub languages: Vec<String>,
+    /// compressed images from `data-saver`
+    pub data_saver: bool,
+}
+
+impl Default for MangaDexOptions {
+    fn default() -> Self {
+        Self {
+            languages: vec!["en".to_string()],
+            data_saver: false,
+        }
+    }
+}
+
+fn get(client: &Client, url: String) -> RequestBuilder {
+    client.get(url).header(USER_AGENT, UA)
+}
+
+/// `https://mangadex.org/title/{id}/{slug}` and `https://mangadex.org/chapter/{id}`
+fn get_id<'a>(url: &'a str, kind: &str) -> Result<&'a str, ScrapeError> {
+    let mut id = url
+        .split_once(&format!("mangadex.org/{kind}/"))
+        .ok_or(ScrapeError::invalid_url("not a mangadex url"))?
+        .1;
+    if let Some((v, _)) = id.split_once(['/', '?']) {
+        id = v;
+    }
+    Ok(id)
+}
+
+pub fn get_valid() -> ValidSearch {
+    ValidSearch {
+        sorts: SORTS.iter().map(|v| v.0.to_string()).collect(),
+        tags: TAGS
+            .iter()
+            .chain(DEMOGRAPHICS.iter())
+            .map(|v| v.to_string())
+            .collect(),
+        status: STATUS.iter().map(|v| v.0.to_string()).collect(),
+    }
+}
+
+pub async fn search(client: &Client, mut search: SimpleSearch) -> Result<SearchPage, ScrapeError> {
+    let vocabulary = TAGS
+        .iter()
+        .chain(DEMOGRAPHICS.iter())
+        .copied()
+        .collect::<Vec<_>>();
+    search.tags = translate_tags_to(&search.tags, &vocabulary);
+    if !search.validate(&get_valid()) {
+        return Err(ScrapeError::input_error("couldnt match ValidSearch"));
+    }
+    let offset = (search.page.max(1) - 1) * SEARCH_LIMIT;
+    let mut items = vec![
+        format!("limit={SEARCH_LIMIT}"),
+        format!("offset={offset}"),
+        "includes[]=cover_art".to_string(),
+    ];
+    if !search.search.is_empty() {
+        items.push(format!("title={}", urlencoding::encode(&search.search)));
+    }
+    let order = match search.desc {
+        true => "desc",
+        false => "asc",
+    };
+    match &search.sort {
+        Some(sort) => {
+            let sort = SORTS.iter().find(|v| v.0 == sort).unwrap().1;
+            items.push(format!("order[{sort}]={order}"))
+        }
+        None if !search.search.is_empty() => items.push("order[relevance]=desc".to_string()),
+        None => items.push("order[followedCount]=desc".to_string()),
+    }
+    if let Some(status) = &search.status {
+        let status = STATUS.iter().find(|v| v.0 == status).unwrap().1;
+        items.push(format!("status[]={status}"));
+    }
+    if !search.tags.is_empty() {
+        let ids = tag_ids(client).await?;
+        for tag in &search.tags {
+            if DEMOGRAPHICS.contains(&tag.as_str()) {
+                items.push(format!("publicationDemographic[]={}", tag.to_lowercase()));
+            } else {
+                let id = ids
+                    .get(&tag.to_lowercase())
+                    .ok_or(ScrapeError::input_error(format!("unknown tag: {tag}")))?;
+                items.push(format!("includedTags[]={id}"));
+            }
+        }
+    }
+    let resp = download(get(client, format!("{API}/manga?{}", items.join("&")))).await?;
+    let data: ListResponse<Manga> = serde_json::from_str(&resp)?;
+    let external_ids = data
+        .data
+        .iter()
+        .map(|manga| {
+            let url = format!("https://mangadex.org/title/{}", manga.id);
+            (url, links(&manga.attributes.links).collect())
+        })
+        .collect();
+    let results = data
+        .data
+        .into_iter()
+        .map(|manga| ScrapeSearchResult {
+            title: manga
+                .attributes
+                .title
+                .values()
+                .next()
+                .cloned()
+                .unwrap_or_default(),
+            url: format!("https://mangadex.org/title/{}", manga.id),
+            cover: cover_url(&manga).unwrap_or_default(),
+            r#type: manga.attributes.original_language.clone(),
+            status: manga.attributes.status.clone(),
+        })
+        .collect::<Vec<_>>();
+    Ok(SearchPage {
+        has_next: offset + (results.len() as u32) < data.total,
+        page: search.page,
+        total: Some(data.total as u64),
+        results,
+        external_ids,
+    })
+}
+
+/// lowercase name => uuid
+async fn tag_ids(client: &Client) -> Result<&'static HashMap<String, String>, ScrapeError> {
+    static TAG_IDS: OnceCell<HashMap<String, String>> = OnceCell::const_new();
+    TAG_IDS
+        .get_or_try_init(|| async {
+            let resp = download(get(client, format!("{API}/manga/tag"))).await?;
+            let data: ListResponse<Tag> = serde_json::from_str(&resp)?;
+            Ok(data
+                .data
+                .into_iter()
+                .filter_map(|v| {
+                    let name = v.attributes.name.get("en")?.to_lowercase();
+                    Some((name, v.id))
+                })
+                .collect())
+        })
+        .await
+}
+
+fn cover_url(manga: &Manga) -> Option<String> {
+    manga
+        .relationships
+        .iter()
+        .find(|v| v.r#type == "cover_art")
+        .and_then(|v| v.attributes.as_ref()?.get("fileName")?.as_str())
+        .map(|file| format!("https://uploads.mangadex.org/covers/{}/{file}", manga.id))
+}
+
+pub async fn get_data(client: &Client, url: &str) -> Result<MangaMetadata, ScrapeError> {
+    let id = get_id(url, "title")?;
+    let url = format!("{API}/manga/{id}?includes[]=cover_art&includes[]=author&includes[]=artist");
+    let resp = download(get(client, url)).await?;
+    let manga = serde_json::from_str::<EntityResponse<Manga>>(&resp)?.data;
+    let cover = cover_url(&manga);
+    let attributes = manga.attributes;
+    let mut res = MangaMetadata {
+        cover,
+        description: attributes
+            .description
+            .get("en")
+            .or(attributes.description.values().next())
+            .cloned(),
+        status: attributes.status.as_deref().and_then(Status::parse),
+        kind: attributes.original_language.as_deref().map(|v| {
+            match v {
+                "ko" => "manhwa",
+                "zh" | "zh-hk" => "manhua",
+                _ => "manga",
+            }
+            .to_string()
+        }),
+        year: attributes.year,
+        latest_chapter: attributes.last_chapter.and_then(|v| v.parse().ok()),
+        ..Default::default()
+    };
+    for (lang, title) in attributes.title {
+        res.titles.push(Title::new(title, Some(lang.as_str())));
+    }
+    for title in attributes.alt_titles {
+        for (lang, title) in title {
+            res.titles.push(Title::new(title, Some(lang.as_str())));
+        }
+    }
+    for tag in attributes.tags {
+        if let Some(name) = tag.attributes.name.get("en") {
+            match tag.attributes.group.as_deref() {
+                Some("genre") => res.genres.push(name.clone()),
+                _ => res.tags.push(name.clone()),
+            }
+        }
+    }
+    if let Some(demographic) = attributes.publication_demographic {
+        res.genres.push(demographic);
+    }
+    for relation in manga.relationships {
+        let name = relation
+            .attributes
+            .as_ref()
+            .and_then(|v| v.get("name"))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string());
+        match (relation.r#type.as_str(), name) {
+            ("author" | "artist", Some(name)) => {
+                res.add_person(name, &relation.r#type, Some(relation.id))
+            }
+            ("manga", _) => res.relations.push(Relation {
+                kind: relation.related.unwrap_or_default(),
+                title: None,
+                media_type: Some("MANGA".to_string()),
+                target: Some(("mangadex".to_string(), relation.id)),
+            }),
+            _ => {}
+        }
+    }
+    res.external_ids
+        .insert("mangadex".to_string(), manga.id.clone());
+    res.external_ids.extend(links(&attributes.links));
+    if let Some(rating) = attributes.content_rating {
+        res.insert_extra("content_rating", rating);
+    }
+    if let Some(lang) = attributes.original_language {
+        res.insert_extra("original_language", lang);
+    }
+    Ok(res)
+}
+
+/// Walks the whole feed of every configured language
+pub async fn get_chapters(
+    client: &Client,
+    url: &str,
+    options: &MangaDexOptions,
+) -> Result<(Vec<Info>, Vec<Info>), ScrapeError> {
+    let id = get_id(url, "title")?;
+    let languages = options
+        .languages
+        .iter()
+        .map(|v| format!("translatedLanguage[]={v}"))
+        .collect::<Vec<_>>()
+        .join("&");
+    let mut res = vec![];
+    let mut offset = 0;
+    let now = date::now();
+    loop {
+        let url = format!("{API}/manga/{id}/feed?limit={FEED_LIMIT}&offset={offset}&{languages}&order[chapter]=asc&includes[]=scanlation_group");
+        let resp = download(get(client, url)).await?;
+        let feed: ListResponse<Chapter> = serde_json::from_str(&resp)?;
+        let len = feed.data.len() as u32;
+        for chapter in feed.data {
+            // hosted on another site, so there are no pages to load
+            if chapter.attributes.external_url.is_some() {
+                continue;
+            }
+            let (episode, unparsed) = chapter_number(
+                chapter.attributes.chapter.as_deref(),
+                chapter.attributes.title.as_deref(),
+            );
+            let groups = chapter
+                .relationships
+                .iter()
+                .filter(|v| v.r#type == "scanlation_group")
+                .filter_map(|v| v.attributes.as_ref()?.get("name")?.as_str())
+                .map(|v| v.to_string())
+                .collect();
+            let attributes = chapter.attributes;
+            res.push(
+                Info {
+                    site: "mangadex".to_string(),
+                    url: format!("https://mangadex.org/chapter/{}", chapter.id),
+                    episode,
+                    unparsed,
+                    language: Some(attributes.translated_language),
+                    groups,
+                    volume: attributes.volume.and_then(|v| v.parse().ok()),
+                    release_at: attributes
+                        .publish_at
+                        .as_deref()
+                        .and_then(|v| parse_date(v, now))
+                        .filter(|v| *v > now),
+                    published_at: attributes.publish_at,
+                    page_count: Some(attributes.pages),
+                    ..Default::default()
+                }
+                .add_title(&attributes.title),
+            );
+        }
+        offset += len;
+        if len == 0 || offset >= feed.total {
+            break;
+        }
+    }
+    Ok(res.into_iter().partition(|v| v.release_at.is_none()))
+}
+
+/// (uri, id) of the sites mangadex links to
+fn links(links: &HashMap<String, String>) -> impl Iterator<Item = (String, String)> + '_ {
+    links.iter().filter_map(|(key, value)| {
+        let uri = match key.as_str() {
+            "al" => "anilist",
+            "kt" => "kitsu",
+            "mu" => "manga-updates",
+            "mal" => "myanimelist",
+            "ap" => "anime-planet",
+            _ => return None,
+        };
+        Some((uri.to_string(), value.clone()))
+    })
+}
+
+/// Oneshots have no chapter and use the number in their title.
+/// Chapters like `5a` and oneshots without a number are flagged, so the [`DuplicatePolicy`] decides what happens to them
+///
+/// [`DuplicatePolicy`]: crate::DuplicatePolicy
+fn chapter_number(chapter: Option<&str>, title: Option<&str>) -> (f64, bool) {
+    match chapter.map(str::trim) {
+        Some(v) => match v.parse() {
+            Ok(v) => (v, false),
+            Err(_) => match Episode::parse(v).chapter {
+                Some(v) => (v, false),
+                None => (0.0, true),
+            },
+        },
+        None => match title.and_then(|v| Episode::parse(v).chapter) {
+            Some(v) => (v, false),
+            None => (0.0, true),
+        },
+    }
+}
+
+pub async fn get_pages(
+    client: &Client,
+    info: &Info,
+    options: &MangaDexOptions,
+) -> Result<Vec<String>, ScrapeError> {
+    let id = get_id(&info.url, "chapter")?;
+    let resp = download(get(client, format!("{API}/at-home/server/{id}"))).await?;
+    let server: AtHomeResponse = serde_json::from_str(&resp)?;
+    let (folder, files) = match options.data_saver {
+        true => ("data-saver", server.chapter.data_saver),
+        false => ("data", server.chapter.data),
+    };
+    Ok(files
+        .into_iter()
+        .map(|file| {
+            format!(
+                "{}/{folder}/{}/{file}",
+                server.base_url, server.chapter.hash
+            )
+        })
+        .collect())
+}
+
+const SORTS: [(&str, &str); 6] = [
+    ("relevance", "relevance"),
+    ("popularity", "followedCount"),
+    ("rating", "rating"),
+    ("updated", "latestUploadedChapter"),
+    ("created", "createdAt"),
+    ("title", "title"),
+];
+
+const STATUS: [(&str, &str); 4] = [
+    ("releasing", "ongoing"),
+    ("finished", "completed"),
+    ("hiatus", "hiatus"),
+    ("cancelled", "cancelled"),
+];
+
+const DEMOGRAPHICS: [&str; 4] = ["Shounen", "Shoujo", "Josei", "Seinen"];
+
+const TAGS: [&str; 76] = [
+    "4-Koma",
+    "Action",
+    "Adaptation",
+    "Adventure",
+    "Aliens",
+    "Animals",
+    "Anthology",
+    "Award Winning",
+    "Boys' Love",
+    "Comedy",
+    "Cooking",
+    "Crime",
+    "Crossdressing",
+    "Delinquents",
+    "Demons",
+    "Doujinshi",
+    "Drama",
+    "Fan Colored",
+    "Fantasy",
+    "Full Color",
+    "Genderswap",
+    "Ghosts",
+    "Girls' Love",
+    "Gore",
+    "Gyaru",
+    "Harem",
+    "Historical",
+    "Horror",
+    "Incest",
+    "Isekai",
+    "Loli",
+    "Long Strip",
+    "Mafia",
+    "Magic",
+    "Magical Girls",
+    "Martial Arts",
+    "Mecha",
+    "Medical",
+    "Military",
+    "Monster Girls",
+    "Monsters",
+    "Music",
+    "Mystery",
+    "Ninja",
+    "Office Workers",
+    "Official Colored",
+    "Oneshot",
+    "Philosophical",
+    "Police",
+    "Post-Apocalyptic",
+    "Psychological",
+    "Reincarnation",
+    "Reverse Harem",
+    "Romance",
+    "Samurai",
+    "School Life",
+    "Sci-Fi",
+    "Self-Published",
+    "Sexual Violence",
+    "Shota",
+    "Slice of Life",
+    "Sports",
+    "Superhero",
+    "Supernatural",
+    "Survival",
+    "Thriller",
+    "Time Travel",
+    "Traditional Games",
+    "Tragedy",
+    "Vampires",
+    "Video Games",
+    "Villainess",
+    "Virtual Reality",
+    "Web Comic",
+    "Wuxia",
+    "Zombies",
+];
+
+#[derive(Serialize, Deserialize)]
+struct ListResponse<T> {
+    pub data: Vec<T>,
+    #[serde(default)]
+    pub total: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EntityResponse<T> {
+    pub data: T,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Relationship {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub related: Option<String>,
+    pub attributes: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TagAttributes {
+    pub name: HashMap<String, String>,
+    pub group: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Tag {
+    pub id: String,
+    pub attributes: TagAttributes,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MangaAttributes {
+    pub title: HashMap<String, String>,
+    #[serde(default)]
+    pub alt_titles: Vec<HashMap<String, String>>,
+    #[serde(default)]
+    pub description: HashMap<String, String>,
+    #[serde(default)]
+    pub links: HashMap<String, String>,
+    pub original_language: Option<String>,
+    pub last_chapter: Option<String>,
+    pub publication_demographic: Option<String>,
+    pub status: Option<String>,
+    pub year: Option<i32>,
+    pub content_rating: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manga {
+    pub id: String,
+    pub attributes: MangaAttributes,
+    #[serde(default)]
+    pub relationships: Vec<Relationship>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChapterAttributes {
+    pub volume: Option<String>,
+    pub chapter: Option<String>,
+    pub title: Option<String>,
+    pub translated_language: String,
+    pub publish_at: Option<String>,
+    pub pages: u32,
+    pub external_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Chapter {
+    pub id: String,
+    pub attributes: ChapterAttributes,
+    #[serde(default)]
+    pub relationships: Vec<Relationship>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AtHomeChapter {
+    pub hash: String,
+    pub data: Vec<String>,
+    pub data_saver: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AtHomeResponse {
+    pub base_url: String,
+    pub chapter: AtHomeChapter,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chapter_numbers() {
+        assert_eq!(chapter_number(Some("12.5"), None), (12.5, false));
+        assert_eq!(chapter_number(Some(" 3 "), None), (3.0, false));
+        assert_eq!(chapter_number(Some("Ch. 7"), None), (7.0, false));
+        assert_eq!(chapter_number(Some("5a"), None), (0.0, true));
+        assert_eq!(chapter_number(None, Some("Oneshot")), (0.0, true));
+        assert_eq!(chapter_number(None, Some("Ch. 4")), (4.0, false));
+        assert_eq!(chapter_number(None, None), (0.0, true));
+    }
+}
@@ -1,82 +1,158 @@
 use crate::downloader::download;
-use crate::services::metadata::ItemOrArray;
+use crate::extractor::feed::parse_feed;
+use crate::services::feed;
+use crate::services::metadata::model::{MangaMetadata, Publisher, Relation, Status, Title};
+use crate::services::multisite::Info;
 use crate::ScrapeError;
 
 use regex::Regex;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::sync::OnceLock;
 
-pub async fn get_data(
-    client: &Client,
-    url: &str,
-) -> Result<HashMap<String, ItemOrArray>, ScrapeError> {
+pub async fn get_data(client: &Client, url: &str) -> Result<MangaMetadata, ScrapeError> {
+    let id = series_id(client, url).await?;
+    let url = format!("https://api.mangaupdates.com/v1/series/{}", id);
     let text = download(client.get(url)).await?;
-    let re = Regex::new(r#"https://api\.mangaupdates\.com/v1/series/([a-zA-Z0-9]+)/rss"#).unwrap();
-    if let Some(v) = re.captures(&text) {
-        let url = format!("https://api.mangaupdates.com/v1/series/{}", &v[1]);
-        let text = download(client.get(url)).await?;
-        let mut hm = HashMap::new();
-        let json: InfoResponse = serde_json::from_str(&text)?;
-        let mut titles = vec![json.title];
-        titles.append(&mut json.associated.into_iter().map(|v| v.title).collect());
-        hm.insert("titles", ItemOrArray::Array(titles));
-        hm.insert("url", ItemOrArray::Item(json.url));
-        hm.insert("img", ItemOrArray::Item(json.image.url.original));
-        hm.insert("description", ItemOrArray::Item(json.description));
-        hm.insert("type", ItemOrArray::Item(json.r#type));
-        let mut tags: Vec<String> = json.genres.into_iter().map(|v| v.genre).collect();
-        tags.append(&mut json.categories.into_iter().map(|c| c.category).collect());
-        hm.insert("tags", ItemOrArray::Array(tags));
-        hm.insert("year", ItemOrArray::Item(json.year));
-        hm.insert("status", ItemOrArray::Item(json.status));
-        hm.insert("licensed", ItemOrArray::Item(json.licensed.to_string()));
-        hm.insert("completed", ItemOrArray::Item(json.completed.to_string()));
-        hm.insert(
-            "related",
-            ItemOrArray::Array(
-                json.related_series
-                    .into_iter()
-                    .map(|v| {
-                        format!(
-                            "{}: {} | {}",
-                            v.relation_type,
-                            v.related_series_name.unwrap_or("UNKNOWN".to_string()),
-                            v.related_series_id
-                        )
-                    })
-                    .collect(),
-            ),
-        );
-        hm.insert(
-            "authors",
-            ItemOrArray::Array(
-                json.authors
-                    .into_iter()
-                    .map(|v| format!("{}: {}", v.r#type, v.name))
-                    .collect(),
-            ),
-        );
-        hm.insert(
-            "publishers",
-            ItemOrArray::Array(
-                json.publishers
-                    .into_iter()
-                    .map(|v| format!("{}: {}", v.r#type, v.publisher_name))
-                    .collect(),
-            ),
-        );
-        Ok(hm
+    let json: InfoResponse = serde_json::from_str(&text)?;
+    let status = match json.completed {
+        true => Some(Status::Finished),
+        false => Status::parse(&json.status),
+    };
+    let mut res = MangaMetadata {
+        cover: Some(json.image.url.original),
+        description: Some(json.description),
+        kind: Some(json.r#type),
+        genres: json.genres.into_iter().map(|v| v.genre).collect(),
+        tags: json.categories.into_iter().map(|c| c.category).collect(),
+        year: json.year.parse().ok(),
+        status,
+        rating: json.bayesian_rating,
+        rank: json.rank.position.year,
+        latest_chapter: json.latest_chapter.map(|v| v as f64),
+        publishers: json
+            .publishers
             .into_iter()
-            .map(|(key, value)| (key.to_string(), value))
-            .collect())
-    } else {
-        Err(ScrapeError::node_not_found())
+            .map(|v| Publisher {
+                name: v.publisher_name,
+                kind: Some(v.r#type),
+            })
+            .collect(),
+        relations: json
+            .related_series
+            .into_iter()
+            .map(|v| Relation {
+                kind: v.relation_type,
+                title: v.related_series_name,
+                media_type: None,
+                target: Some(("manga-updates".to_string(), v.related_series_id.to_string())),
+            })
+            .collect(),
+        ..Default::default()
+    };
+    res.titles.push(Title::new(json.title, None));
+    for title in json.associated {
+        res.titles.push(Title::new(title.title, None));
+    }
+    for author in json.authors {
+        res.add_person(author.name, &author.r#type, None);
+    }
+    res.external_ids
+        .insert("manga-updates".to_string(), json.series_id.to_string());
+    res.insert_extra("url", json.url);
+    res.insert_extra("status", json.status);
+    res.insert_extra("licensed", json.licensed);
+    if let Some(start) = json.anime.start {
+        res.insert_extra("anime_start", start);
+    }
+    if let Some(end) = json.anime.end {
+        res.insert_extra("anime_end", end);
+    }
+    for (key, rank) in [
+        ("rank_week", json.rank.position.week),
+        ("rank_month", json.rank.position.month),
+        ("rank_three_months", json.rank.position.three_months),
+        ("rank_six_months", json.rank.position.six_months),
+    ] {
+        if let Some(rank) = rank {
+            res.insert_extra(key, rank);
+        }
+    }
+    Ok(res)
+}
+
+/// Releases of the rss feed, they link to the groups and not to a reader
+pub async fn get_releases(client: &Client, url: &str) -> Result<Vec<Info>, ScrapeError> {
+    let id = series_id(client, url).await?;
+    let url = format!("https://api.mangaupdates.com/v1/series/{id}/rss");
+    let xml = download(client.get(url)).await?;
+    Ok(parse_feed(&xml)
+        .into_iter()
+        .map(|v| feed::to_info("manga-updates", v))
+        .collect())
+}
+
+/// Supports `/series/{base36 id}/{slug}`, the legacy `series.html?id={id}` and api urls
+pub(crate) async fn series_id(client: &Client, url: &str) -> Result<i64, ScrapeError> {
+    if let Some(id) = parse_series_id(url) {
+        return Ok(id);
+    }
+    if url.contains("series.html?id=") {
+        // the legacy ids differ from the api ids, but the site redirects to the new url
+        let resp = client.get(url).send().await?;
+        if let Some(id) = parse_series_id(resp.url().as_str()) {
+            return Ok(id);
+        }
+    }
+    Err(ScrapeError::invalid_url(format!(
+        "couldnt find mangaupdates series id in {url}"
+    )))
+}
+
+/// Inverse of [`parse_series_id`], the site uses base36 ids
+pub(crate) fn series_url(id: i64, key: &str) -> String {
+    let mut n = id.unsigned_abs();
+    let mut digits = vec![];
+    loop {
+        digits.push(std::char::from_digit((n % 36) as u32, 36).unwrap());
+        n /= 36;
+        if n == 0 {
+            break;
+        }
+    }
+    let id = digits.into_iter().rev().collect::<String>();
+    match key.is_empty() {
+        true => format!("https://www.mangaupdates.com/series/{id}"),
+        false => format!("https://www.mangaupdates.com/series/{id}/{key}"),
+    }
+}
+
+struct Patterns {
+    api: Regex,
+    site: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        api: Regex::new(r"api\.mangaupdates\.com/v1/series/(\d+)").unwrap(),
+        site: Regex::new(r"mangaupdates\.com/series/([a-zA-Z0-9]+)(/|$|\?)").unwrap(),
+    })
+}
+
+/// Legacy urls have other ids and are None
+fn parse_series_id(url: &str) -> Option<i64> {
+    let p = patterns();
+    if let Some(v) = p.api.captures(url) {
+        return v[1].parse().ok();
     }
+    p.site
+        .captures(url)
+        .and_then(|v| i64::from_str_radix(&v[1], 36).ok())
 }
 
 #[derive(Serialize, Deserialize)]
-struct Publisher {
+struct PublisherResponse {
     pub publisher_name: String,
     #[serde(rename = "type")]
     pub r#type: String,
@@ -114,6 +190,7 @@ struct OtherTitles {
 
 #[derive(Serialize, Deserialize)]
 struct InfoResponse {
+    pub series_id: i64,
     pub title: String,
     pub url: String,
     pub associated: Vec<OtherTitles>,
@@ -129,7 +206,34 @@ struct InfoResponse {
     pub completed: bool,
     pub related_series: Vec<RelatedSeries>,
     pub authors: Vec<Author>,
-    pub publishers: Vec<Publisher>,
+    pub publishers: Vec<PublisherResponse>,
+    pub bayesian_rating: Option<f64>,
+    pub latest_chapter: Option<i64>,
+    #[serde(default)]
+    pub anime: Anime,
+    #[serde(default)]
+    pub rank: Rank,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Anime {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Rank {
+    #[serde(default)]
+    pub position: RankPosition,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct RankPosition {
+    pub week: Option<u64>,
+    pub month: Option<u64>,
+    pub three_months: Option<u64>,
+    pub six_months: Option<u64>,
+    pub year: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -143,3 +247,45 @@ struct RelatedSeries {
 struct Category {
     pub category: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_ids() {
+        let cases = [
+            (
+                "https://www.mangaupdates.com/series/pb8uwds/one-piece",
+                Some(55099564912),
+            ),
+            (
+                "https://www.mangaupdates.com/series/pb8uwds",
+                Some(55099564912),
+            ),
+            (
+                "https://mangaupdates.com/series/pb8uwds?tab=releases",
+                Some(55099564912),
+            ),
+            (
+                "https://api.mangaupdates.com/v1/series/55099564912",
+                Some(55099564912),
+            ),
+            (
+                "https://api.mangaupdates.com/v1/series/55099564912/rss",
+                Some(55099564912),
+            ),
+            ("https://www.mangaupdates.com/series.html?id=33", None),
+            ("https://www.mangaupdates.com/series/", None),
+            ("https://mangadex.org/title/pb8uwds", None),
+        ];
+        for (url, id) in cases {
+            assert_eq!(parse_series_id(url), id, "{url}");
+        }
+        assert_eq!(
+            series_url(55099564912, "one-piece"),
+            "https://www.mangaupdates.com/series/pb8uwds/one-piece"
+        );
+        assert_eq!(series_url(35, ""), "https://www.mangaupdates.com/series/z");
+    }
+}
@@ -1,2 +1,4 @@
 pub mod data;
+pub mod query;
 pub mod search;
+pub mod sql;
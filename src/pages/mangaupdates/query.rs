@@ -0,0 +1,639 @@
+use crate::pages::mangaupdates::search::{
+    Array, IdOrValue, Item, ItemData, ItemOrArray, Order, OrderKind, SearchRequest,
+};
+use crate::pages::tags::{translate_tag, TagSource};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Invalid query, `position` is the index of the char that caused it
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new(position: usize, message: impl ToString) -> Self {
+        Self {
+            position,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Bigger,
+    BiggerEq,
+    Smaller,
+    SmallerEq,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    Or,
+    And,
+    /// words without a key are part of the title
+    Word(String),
+    Filter {
+        key: String,
+        op: Op,
+        value: String,
+        value_pos: usize,
+    },
+}
+
+/// Parses queries like `tag:"time travel" genre:action -genre:harem year>=2015 (author:oda or artist:oda)`.
+///
+/// - terms next to each other have to match, `or` between them only needs one of them
+/// - `-` or `not` negates a term or group
+/// - words without a key search the titles, as do words with an unknown key like `Re:Zero`.
+///   `sort:title` or `sort:-updated` changes the order.
+///   Title searches are sorted by relevance
+pub fn parse_query(input: &str) -> Result<SearchRequest, QueryError> {
+    let tokens = tokenize(input)?;
+    let mut parser = Parser {
+        tokens,
+        index: 0,
+        end: input.chars().count(),
+        order: None,
+    };
+    let data = match parser.or()? {
+        Some(ItemOrArray::Array(v)) => v,
+        Some(item) => Array {
+            or: false,
+            items: vec![item],
+        },
+        None => Array {
+            or: false,
+            items: vec![],
+        },
+    };
+    if let Some((pos, _)) = parser.tokens.get(parser.index) {
+        return Err(QueryError::new(*pos, "unexpected `)`"));
+    }
+    // searches for a title show the best matches first
+    let kind = match data.titles().is_empty() {
+        true => OrderKind::Title,
+        false => OrderKind::Relevance,
+    };
+    Ok(SearchRequest {
+        data,
+        order: parser.order.unwrap_or(Order { desc: false, kind }),
+    })
+}
+
+impl FromStr for SearchRequest {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_query(s)
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, QueryError> {
+    let chars = input.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        match chars[i] {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push((start, Token::Open));
+                i += 1;
+            }
+            ')' => {
+                tokens.push((start, Token::Close));
+                i += 1;
+            }
+            '-' if chars.get(i + 1).is_some_and(|c| !c.is_whitespace()) => {
+                tokens.push((start, Token::Not));
+                i += 1;
+            }
+            '"' => {
+                let (value, end) = quoted(&chars, i)?;
+                tokens.push((start, Token::Word(value)));
+                i = end;
+            }
+            _ => {
+                while i < chars.len() && !is_separator(chars[i]) && !is_op(chars[i]) {
+                    i += 1;
+                }
+                let word = chars[start..i].iter().collect::<String>();
+                let op_follows = i < chars.len() && is_op(chars[i]);
+                if op_follows && word.is_empty() {
+                    return Err(QueryError::new(start, "missing key before operator"));
+                }
+                if op_follows && is_key(&word.to_lowercase()) {
+                    let (op, len) = match (chars[i], chars.get(i + 1)) {
+                        ('>', Some('=')) => (Op::BiggerEq, 2),
+                        ('<', Some('=')) => (Op::SmallerEq, 2),
+                        ('>', _) => (Op::Bigger, 1),
+                        ('<', _) => (Op::Smaller, 1),
+                        _ => (Op::Eq, 1),
+                    };
+                    i += len;
+                    let value_pos = i;
+                    let value = match chars.get(i) {
+                        Some('"') => {
+                            let (value, end) = quoted(&chars, i)?;
+                            i = end;
+                            value
+                        }
+                        _ => {
+                            while i < chars.len() && !is_separator(chars[i]) {
+                                i += 1;
+                            }
+                            chars[value_pos..i].iter().collect()
+                        }
+                    };
+                    if value.is_empty() {
+                        return Err(QueryError::new(
+                            value_pos,
+                            format!("missing value for `{word}`"),
+                        ));
+                    }
+                    tokens.push((
+                        start,
+                        Token::Filter {
+                            key: word.to_lowercase(),
+                            op,
+                            value,
+                            value_pos,
+                        },
+                    ));
+                } else {
+                    // titles like `Re:Zero` or `Fate/Zero: X` aren't filters
+                    while i < chars.len() && !is_separator(chars[i]) {
+                        i += 1;
+                    }
+                    let word = chars[start..i].iter().collect::<String>();
+                    let token = match word.to_lowercase().as_str() {
+                        "or" => Token::Or,
+                        "and" => Token::And,
+                        "not" => Token::Not,
+                        _ => Token::Word(word),
+                    };
+                    tokens.push((start, token));
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// keys handled by [`Parser::filter`], other words before an operator are part of the title
+fn is_key(key: &str) -> bool {
+    matches!(
+        key,
+        "sort"
+            | "order"
+            | "id"
+            | "pid"
+            | "private_id"
+            | "public_id"
+            | "forum_id"
+            | "key"
+            | "url_key"
+            | "title"
+            | "description"
+            | "desc"
+            | "type"
+            | "tag"
+            | "genre"
+            | "licensed"
+            | "completed"
+            | "artist"
+            | "author"
+            | "publisher"
+            | "publisher_eng"
+            | "english_publisher"
+            | "year"
+            | "chapter"
+            | "latest_chapter"
+            | "rating"
+    )
+}
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')'
+}
+
+fn is_op(c: char) -> bool {
+    matches!(c, ':' | '=' | '<' | '>')
+}
+
+/// `start` points at the opening quote, returns the text and the index after the closing quote
+fn quoted(chars: &[char], start: usize) -> Result<(String, usize), QueryError> {
+    let mut value = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                value.push(chars[i + 1]);
+                i += 2;
+            }
+            '"' => return Ok((value, i + 1)),
+            c => {
+                value.push(c);
+                i += 1;
+            }
+        }
+    }
+    Err(QueryError::new(start, "unterminated quote"))
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    /// position reported for errors at the end of the input
+    end: usize,
+    order: Option<Order>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|v| &v.1)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.index).map(|v| v.0).unwrap_or(self.end)
+    }
+
+    fn or(&mut self) -> Result<Option<ItemOrArray>, QueryError> {
+        let mut items = vec![];
+        items.extend(self.and()?);
+        while self.peek() == Some(&Token::Or) {
+            self.index += 1;
+            match self.and()? {
+                Some(v) => items.push(v),
+                None => {
+                    return Err(QueryError::new(
+                        self.position(),
+                        "expected a filter after `or`",
+                    ))
+                }
+            }
+        }
+        Ok(combine(items, true))
+    }
+
+    fn and(&mut self) -> Result<Option<ItemOrArray>, QueryError> {
+        let mut items = vec![];
+        loop {
+            match self.peek() {
+                None | Some(Token::Close) | Some(Token::Or) => break,
+                Some(Token::And) => {
+                    self.index += 1;
+                    if matches!(self.peek(), None | Some(Token::Close) | Some(Token::Or)) {
+                        return Err(QueryError::new(
+                            self.position(),
+                            "expected a filter after `and`",
+                        ));
+                    }
+                }
+                _ => items.extend(self.unary()?),
+            }
+        }
+        Ok(combine(items, false))
+    }
+
+    fn unary(&mut self) -> Result<Option<ItemOrArray>, QueryError> {
+        let (pos, token) = self.tokens[self.index].clone();
+        self.index += 1;
+        match token {
+            Token::Not => {
+                if matches!(
+                    self.peek(),
+                    None | Some(Token::Close) | Some(Token::Or) | Some(Token::And)
+                ) {
+                    return Err(QueryError::new(
+                        self.position(),
+                        "expected a filter to negate",
+                    ));
+                }
+                match self.unary()? {
+                    Some(v) => Ok(Some(negate(v))),
+                    None => Err(QueryError::new(pos, "`sort` can't be negated")),
+                }
+            }
+            Token::Open => {
+                let inner = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(QueryError::new(pos, "missing `)` for this `(`"));
+                }
+                self.index += 1;
+                match inner {
+                    Some(v) => Ok(Some(v)),
+                    None => Err(QueryError::new(pos, "empty group")),
+                }
+            }
+            Token::Word(word) => {
+                let mut title = word;
+                while let Some(Token::Word(next)) = self.peek() {
+                    title.push(' ');
+                    title.push_str(next);
+                    self.index += 1;
+                }
+                Ok(Some(item(ItemData::Title(title))))
+            }
+            Token::Filter {
+                key,
+                op,
+                value,
+                value_pos,
+            } => self.filter(pos, &key, op, value, value_pos),
+            Token::Close | Token::Or | Token::And => unreachable!(),
+        }
+    }
+
+    fn filter(
+        &mut self,
+        pos: usize,
+        key: &str,
+        op: Op,
+        value: String,
+        value_pos: usize,
+    ) -> Result<Option<ItemOrArray>, QueryError> {
+        let comparable = matches!(key, "year" | "chapter" | "latest_chapter" | "rating");
+        if op != Op::Eq && !comparable {
+            return Err(QueryError::new(
+                pos,
+                format!(
+                    "`{key}` only supports `:`, comparisons work with year, chapter and rating"
+                ),
+            ));
+        }
+        let data = match key {
+            "sort" | "order" => {
+                self.order = Some(order(&value, value_pos)?);
+                return Ok(None);
+            }
+            "id" => ItemData::Id(number(&value, value_pos)?),
+            "pid" | "private_id" => ItemData::Pid(number(&value, value_pos)?),
+            "public_id" => ItemData::PublicId(number(&value, value_pos)?),
+            "forum_id" => ItemData::ForumId(number(&value, value_pos)?),
+            "key" | "url_key" => ItemData::Key(value),
+            "title" => ItemData::Title(value),
+            "description" | "desc" => ItemData::Description(value),
+            "type" => ItemData::Type(id_or_value(value)),
+            "tag" | "genre" => genre_or_tag(key, value),
+            "licensed" => ItemData::Licensed(boolean(&value, value_pos)?),
+            "completed" => ItemData::Completed(boolean(&value, value_pos)?),
+            "artist" => ItemData::Artist(id_or_value(value)),
+            "author" => ItemData::Author(id_or_value(value)),
+            "publisher" => ItemData::Publisher {
+                value: id_or_value(value),
+                eng: false,
+            },
+            "publisher_eng" | "english_publisher" => ItemData::Publisher {
+                value: id_or_value(value),
+                eng: true,
+            },
+            "year" => {
+                let value: i32 = number(&value, value_pos)?;
+                return Ok(Some(compare(op, |eq, bigger| ItemData::Year {
+                    eq,
+                    bigger,
+                    value,
+                })));
+            }
+            "chapter" | "latest_chapter" => {
+                let value: i32 = number(&value, value_pos)?;
+                return Ok(Some(compare(op, |eq, bigger| ItemData::LatestChapter {
+                    eq,
+                    bigger,
+                    value,
+                })));
+            }
+            "rating" => {
+                let rating: f32 = number(&value, value_pos)?;
+                return Ok(Some(compare(op, |eq, bigger| ItemData::Rating {
+                    eq,
+                    bigger,
+                    rating,
+                })));
+            }
+            _ => return Err(QueryError::new(pos, format!("unknown filter `{key}`"))),
+        };
+        Ok(Some(item(data)))
+    }
+}
+
+fn item(data: ItemData) -> ItemOrArray {
+    ItemOrArray::Item(Item { not: false, data })
+}
+
+fn combine(mut items: Vec<ItemOrArray>, or: bool) -> Option<ItemOrArray> {
+    match items.len() {
+        0 => None,
+        1 => items.pop(),
+        _ => Some(ItemOrArray::Array(Array { or, items })),
+    }
+}
+
+/// Negating a group flips `and`/`or` and negates every item
+fn negate(value: ItemOrArray) -> ItemOrArray {
+    match value {
+        ItemOrArray::Item(mut item) => {
+            item.not = !item.not;
+            ItemOrArray::Item(item)
+        }
+        ItemOrArray::Array(array) => ItemOrArray::Array(Array {
+            or: !array.or,
+            items: array.items.into_iter().map(negate).collect(),
+        }),
+    }
+}
+
+/// `year:2015` needs `>=` and `<=` because the items have no plain equal
+fn compare(op: Op, data: impl Fn(bool, bool) -> ItemData) -> ItemOrArray {
+    match op {
+        Op::Eq => ItemOrArray::Array(Array {
+            or: false,
+            items: vec![item(data(true, true)), item(data(true, false))],
+        }),
+        Op::Bigger => item(data(false, true)),
+        Op::BiggerEq => item(data(true, true)),
+        Op::Smaller => item(data(false, false)),
+        Op::SmallerEq => item(data(true, false)),
+    }
+}
+
+fn order(value: &str, pos: usize) -> Result<Order, QueryError> {
+    let (desc, kind) = match value.strip_prefix('-') {
+        Some(v) => (true, v),
+        None => (false, value),
+    };
+    let kind = match kind.to_lowercase().as_str() {
+        "id" => OrderKind::Id,
+        "pid" | "private_id" => OrderKind::PrivateId,
+        "title" => OrderKind::Title,
+        "updated" | "last_updated" => OrderKind::LastUpdatedMU,
+        "relevance" => OrderKind::Relevance,
+        // equal parts similarity and rating
+        "ranked" => OrderKind::RelevanceRating(0.5),
+        _ => {
+            return Err(QueryError::new(
+                pos,
+                format!("unknown sort `{kind}`, valid are id, private_id, title, updated, relevance and ranked"),
+            ))
+        }
+    };
+    Ok(Order { desc, kind })
+}
+
+fn number<T: FromStr>(value: &str, pos: usize) -> Result<T, QueryError> {
+    value
+        .parse()
+        .map_err(|_| QueryError::new(pos, format!("`{value}` is not a valid number")))
+}
+
+fn boolean(value: &str, pos: usize) -> Result<bool, QueryError> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(QueryError::new(
+            pos,
+            format!("`{value}` is not a boolean, use true or false"),
+        )),
+    }
+}
+
+/// Mangaupdates has a short list of genres, everything else is a tag(category).
+/// Names are translated first, so `tag:"girls love"` searches the genre `Shoujo Ai`,
+/// ids keep the table of their key
+fn genre_or_tag(key: &str, value: String) -> ItemData {
+    match id_or_value(translate_tag(&value, TagSource::MangaUpdates)) {
+        IdOrValue::Value(v) if is_genre(&v) => ItemData::Genre(IdOrValue::Value(v)),
+        IdOrValue::Value(v) => ItemData::Tag(IdOrValue::Value(v)),
+        id if key == "genre" => ItemData::Genre(id),
+        id => ItemData::Tag(id),
+    }
+}
+
+/// genres of mangaupdates, the rest are tags
+#[rustfmt::skip]
+const GENRES: [&str; 36] = [
+    "Action", "Adult", "Adventure", "Comedy", "Doujinshi", "Drama", "Ecchi", "Fantasy",
+    "Gender Bender", "Harem", "Hentai", "Historical", "Horror", "Josei", "Lolicon",
+    "Martial Arts", "Mature", "Mecha", "Mystery", "Psychological", "Romance", "School Life",
+    "Sci-fi", "Seinen", "Shotacon", "Shoujo", "Shoujo Ai", "Shounen", "Shounen Ai",
+    "Slice of Life", "Smut", "Sports", "Supernatural", "Tragedy", "Yaoi", "Yuri",
+];
+
+fn is_genre(name: &str) -> bool {
+    GENRES.iter().any(|v| v.eq_ignore_ascii_case(name))
+}
+
+/// numbers are ids, everything else is matched by name
+fn id_or_value(value: String) -> IdOrValue {
+    match value.parse() {
+        Ok(id) => IdOrValue::Id(id),
+        Err(_) => IdOrValue::Value(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn data(input: &str) -> Value {
+        serde_json::to_value(parse_query(input).unwrap().data).unwrap()
+    }
+
+    fn error(input: &str) -> usize {
+        parse_query(input).unwrap_err().position
+    }
+
+    #[test]
+    fn example_query() {
+        let req = parse_query(
+            r#"tag:"time travel" genre:action -genre:harem year>=2015 (author:oda or artist:oda)"#,
+        )
+        .unwrap();
+        assert!(matches!(req.order.kind, OrderKind::Title));
+        assert_eq!(
+            serde_json::to_value(&req.data).unwrap(),
+            json!({"or": false, "items": [
+                {"not": false, "data": {"Tag": "Time Travel"}},
+                {"not": false, "data": {"Genre": "Action"}},
+                {"not": true, "data": {"Genre": "Harem"}},
+                {"not": false, "data": {"Year": {"eq": true, "bigger": true, "value": 2015}}},
+                {"or": true, "items": [
+                    {"not": false, "data": {"Author": "oda"}},
+                    {"not": false, "data": {"Artist": "oda"}},
+                ]},
+            ]})
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_titles() {
+        assert_eq!(
+            data("Re:Zero"),
+            json!({"or": false, "items": [{"not": false, "data": {"Title": "Re:Zero"}}]})
+        );
+        assert_eq!(
+            data("Fate/Zero: X genre:action"),
+            json!({"or": false, "items": [
+                {"not": false, "data": {"Title": "Fate/Zero: X"}},
+                {"not": false, "data": {"Genre": "Action"}},
+            ]})
+        );
+        let req = parse_query("Re:Zero").unwrap();
+        assert!(matches!(req.order.kind, OrderKind::Relevance));
+    }
+
+    #[test]
+    fn genres_and_tags_cross_over() {
+        assert_eq!(
+            data(r#"tag:"girls love" genre:"time travel" tag:7 genre:7"#),
+            json!({"or": false, "items": [
+                {"not": false, "data": {"Genre": "Shoujo Ai"}},
+                {"not": false, "data": {"Tag": "Time Travel"}},
+                {"not": false, "data": {"Tag": 7}},
+                {"not": false, "data": {"Genre": 7}},
+            ]})
+        );
+    }
+
+    #[test]
+    fn sort() {
+        let req = parse_query("one piece sort:-updated").unwrap();
+        assert!(req.order.desc);
+        assert!(matches!(req.order.kind, OrderKind::LastUpdatedMU));
+        assert_eq!(error("sort:newest"), 5);
+        assert_eq!(error("-sort:id"), 0);
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(error(r#"tag:"time travel"#), 4);
+        assert_eq!(error("year:abc"), 5);
+        assert_eq!(error("genre:action completed:maybe"), 23);
+        assert_eq!(error("tag>5"), 0);
+        assert_eq!(error("tag: action"), 4);
+        assert_eq!(error(":action"), 0);
+        assert_eq!(error("(genre:action"), 0);
+        assert_eq!(error("genre:action)"), 12);
+        assert_eq!(error("()"), 0);
+        assert_eq!(error("genre:action or"), 15);
+        assert_eq!(error("genre:action and)"), 16);
+        assert_eq!(error("ö genre:"), 8);
+    }
+}
@@ -1,26 +1,278 @@
-use bytes::Bytes;
-use futures::{pin_mut, stream, SinkExt};
+use crate::pages::mangaupdates::data::series_url;
+use crate::pages::mangaupdates::sql::{contains_pattern, Query, SEARCH_SETUP};
+use crate::services::search::SearchPage;
+use crate::ScrapeError;
+use api_structure::scraper::ScrapeSearchResult;
+use bytes::{Bytes, BytesMut};
+use futures::{pin_mut, SinkExt};
+use openssl::sha::Sha256;
 use pg_embed::pg_enums::PgAuthMethod;
-use pg_embed::pg_errors::PgEmbedError;
 use pg_embed::pg_fetch::{PgFetchSettings, PG_V15};
-use pg_embed::postgres::PgSettings;
+use pg_embed::postgres::{PgEmbed, PgSettings};
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
-use std::fs::read_to_string;
-use std::path::Path;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines};
+use tokio::sync::Mutex;
 
-use tokio_postgres::{CopyInSink, NoTls};
+use tokio_postgres::types::FromSql;
+use tokio_postgres::{CopyInSink, NoTls, Row};
 
-async fn init_postgres(path: &Path, port: u16) -> Result<tokio_postgres::Client, PgEmbedError> {
+const PAGE_SIZE: u32 = 50;
+const DB_NAME: &str = "mangaupdates";
+/// COPY data is sent in chunks of this size, so only one chunk is in memory at a time
+const COPY_CHUNK: usize = 1 << 16;
+
+/// The mangaupdates dump in an embedded postgres, searchable as `mangaupdates-offline`.
+/// The database is started by the first search and stopped with [`OfflineDataset::stop`].
+/// The import is kept in `external/mangaupdates` and only repeated when the dump changes
+pub struct OfflineDataset {
+    root: PathBuf,
+    port: u16,
+    db: Mutex<Option<Arc<Database>>>,
+    /// set once the import is done, so checking it doesn't wait for the lock
+    started: AtomicBool,
+    progress: Option<ProgressFn>,
+}
+
+pub type ProgressFn = Arc<dyn Fn(ImportProgress) + Send + Sync>;
+
+/// Bytes of the dump that are imported
+#[derive(Debug, Clone, Copy)]
+pub struct ImportProgress {
+    pub done: u64,
+    pub total: u64,
+}
+
+struct Database {
+    pg: PgEmbed,
+    client: tokio_postgres::Client,
+}
+
+/// Search results with the rows they were created from
+#[derive(Debug, Default, Serialize)]
+pub struct RecordPage {
+    pub page: SearchPage,
+    pub records: Vec<Record>,
+}
+
+/// Row of the `info` table, ids point into the `genres`, `tags`, `mtypes` and `ppl` tables
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Record {
+    pub id: i32,
+    pub private_id: Option<i32>,
+    pub public_id: i64,
+    pub forum_id: Option<i64>,
+    pub url_key: String,
+    pub titles: Vec<String>,
+    pub description: Option<String>,
+    pub typ: Option<i32>,
+    pub year: Option<i32>,
+    pub latest_chapter: Option<i32>,
+    pub genres: Vec<i32>,
+    pub tags: Vec<i32>,
+    pub licensed: bool,
+    pub completed: bool,
+    pub artist: Vec<i32>,
+    pub author: Vec<i32>,
+    pub publisher_english: Vec<i32>,
+    pub publisher_original: Vec<i32>,
+    pub bayesian_rating: Option<f32>,
+    /// similarity to the searched titles from 0 to 1
+    pub score: Option<f32>,
+}
+
+impl Record {
+    /// Nulls and the columns [`Projection::Slim`] doesn't load fall back to their default
+    fn from_row(row: &Row) -> Result<Self, ScrapeError> {
+        Ok(Self {
+            id: get(row, "id")?.unwrap_or_default(),
+            private_id: omitted(row, "private_id")?,
+            public_id: get(row, "public_id")?.unwrap_or_default(),
+            forum_id: omitted(row, "forum_id")?,
+            url_key: get(row, "url_key")?.unwrap_or_default(),
+            titles: get(row, "titles")?.unwrap_or_default(),
+            description: omitted(row, "description")?,
+            typ: omitted(row, "typ")?,
+            year: omitted(row, "year")?,
+            latest_chapter: omitted(row, "latest_chapter")?,
+            genres: omitted(row, "genres")?.unwrap_or_default(),
+            tags: omitted(row, "tags")?.unwrap_or_default(),
+            licensed: omitted(row, "licensed")?.unwrap_or_default(),
+            completed: get(row, "completed")?.unwrap_or_default(),
+            artist: omitted(row, "artist")?.unwrap_or_default(),
+            author: omitted(row, "author")?.unwrap_or_default(),
+            publisher_english: omitted(row, "publisher_english")?.unwrap_or_default(),
+            publisher_original: omitted(row, "publisher_original")?.unwrap_or_default(),
+            bayesian_rating: omitted(row, "bayesian_rating")?,
+            score: omitted(row, "score")?,
+        })
+    }
+
+    pub fn to_result(&self) -> ScrapeSearchResult {
+        ScrapeSearchResult {
+            title: self.titles.first().cloned().unwrap_or_default(),
+            url: series_url(self.public_id, &self.url_key),
+            cover: String::new(),
+            r#type: None,
+            status: Some(
+                match self.completed {
+                    true => "completed",
+                    false => "ongoing",
+                }
+                .to_string(),
+            ),
+        }
+    }
+}
+
+fn get<'a, T: FromSql<'a>>(row: &'a Row, column: &str) -> Result<Option<T>, ScrapeError> {
+    Ok(row.try_get::<_, Option<T>>(column)?)
+}
+
+/// Like [`get`], but None if the column wasn't selected
+fn omitted<'a, T: FromSql<'a>>(row: &'a Row, column: &str) -> Result<Option<T>, ScrapeError> {
+    match row.columns().iter().any(|v| v.name() == column) {
+        true => get(row, column),
+        false => Ok(None),
+    }
+}
+
+impl OfflineDataset {
+    /// `root` is the root folder of the scraper, it needs `external/mangaupdates.sql`
+    pub fn new(root: impl Into<PathBuf>, port: u16) -> Self {
+        Self {
+            root: root.into(),
+            port,
+            db: Mutex::new(None),
+            started: AtomicBool::new(false),
+            progress: None,
+        }
+    }
+
+    /// Called while the dump is imported, an already imported dump doesn't report anything
+    pub fn on_progress(mut self, progress: ProgressFn) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Starts the database and imports the dump if needed, otherwise the first search does it
+    pub async fn start(&self) -> Result<(), ScrapeError> {
+        self.database().await.map(|_| ())
+    }
+
+    /// False while the dump is still imported
+    pub fn is_started(&self) -> bool {
+        self.started.load(Ordering::Acquire)
+    }
+
+    async fn database(&self) -> Result<Arc<Database>, ScrapeError> {
+        let mut db = self.db.lock().await;
+        if let Some(db) = db.as_ref() {
+            return Ok(db.clone());
+        }
+        let (pg, client) = init_postgres(&self.root, self.port, self.progress.as_ref()).await?;
+        let started = Arc::new(Database { pg, client });
+        *db = Some(started.clone());
+        self.started.store(true, Ordering::Release);
+        Ok(started)
+    }
+
+    /// Full rows of one page
+    pub async fn search(
+        &self,
+        request: &SearchRequest,
+        page: u32,
+    ) -> Result<RecordPage, ScrapeError> {
+        let (page, records) = self.fetch(request, page, Projection::Full).await?;
+        Ok(RecordPage { page, records })
+    }
+
+    /// Only loads the columns needed for the [`SearchPage`]
+    pub async fn results(
+        &self,
+        request: &SearchRequest,
+        page: u32,
+    ) -> Result<SearchPage, ScrapeError> {
+        Ok(self.fetch(request, page, Projection::Slim).await?.0)
+    }
+
+    async fn fetch(
+        &self,
+        request: &SearchRequest,
+        page: u32,
+        projection: Projection,
+    ) -> Result<(SearchPage, Vec<Record>), ScrapeError> {
+        let db = self.database().await?;
+        let offset = (page.max(1) - 1) * PAGE_SIZE;
+        let query = request.query(projection, PAGE_SIZE, offset);
+        let count = request.count();
+        let (params, count_params) = (query.params(), count.params());
+        let (rows, total) = futures::try_join!(
+            db.client.query(&query.sql, &params),
+            db.client.query_one(&count.sql, &count_params)
+        )?;
+        let total = total.get::<_, i64>(0) as u64;
+        let records = rows
+            .iter()
+            .map(Record::from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+        let page = SearchPage {
+            results: records.iter().map(Record::to_result).collect(),
+            page,
+            has_next: offset as u64 + (records.len() as u64) < total,
+            total: Some(total),
+            external_ids: Default::default(),
+        };
+        Ok((page, records))
+    }
+
+    /// Stops the database, the next search starts it again
+    pub async fn stop(&self) -> Result<(), ScrapeError> {
+        let db = {
+            let mut db = self.db.lock().await;
+            self.started.store(false, Ordering::Release);
+            db.take()
+        };
+        // a running search keeps the database until it's done, dropping it stops it as well
+        if let Some(Ok(mut db)) = db.map(Arc::try_unwrap) {
+            db.pg.stop_db().await?;
+        }
+        Ok(())
+    }
+}
+
+async fn init_postgres(
+    path: &Path,
+    port: u16,
+    progress: Option<&ProgressFn>,
+) -> Result<(PgEmbed, tokio_postgres::Client), ScrapeError> {
     //https://huggingface.co/datasets/GriddleDean/mangaupdates/resolve/main/postgres.sql?download=true
-    let mut pg = pg_embed::postgres::PgEmbed::new(
+    let dump = path.join("external/mangaupdates.sql");
+    if !dump.is_file() {
+        return Err(ScrapeError::input_error(format!(
+            "mangaupdates dump is missing: {}",
+            dump.display()
+        )));
+    }
+    let checksum = tokio::task::spawn_blocking({
+        let dump = dump.clone();
+        move || dump_checksum(&dump)
+    })
+    .await
+    .map_err(ScrapeError::input_error)??;
+    let mut pg = PgEmbed::new(
         PgSettings {
             database_dir: path.join("external/mangaupdates"),
             port,
             user: "postgres".to_string(),
             password: "password".to_string(),
             auth_method: PgAuthMethod::Plain,
-            persistent: false,
+            persistent: true,
             timeout: None,
             migration_dir: None,
         },
@@ -29,171 +281,379 @@ async fn init_postgres(path: &Path, port: u16) -> Result<tokio_postgres::Client,
             ..Default::default()
         },
     )
-    .await
-    .unwrap();
-    pg.setup().await.unwrap();
-    // start postgresql database
-    pg.start_db().await.unwrap();
+    .await?;
+    pg.setup().await?;
+    pg.start_db().await?;
+
+    if pg.database_exists(DB_NAME).await? {
+        let client = connect(port).await?;
+        if imported_checksum(&client).await?.as_deref() == Some(checksum.as_str()) {
+            client.batch_execute(SEARCH_SETUP).await?;
+            return Ok((pg, client));
+        }
+        // an older dump or an import that didn't finish
+        drop(client);
+        pg.drop_database(DB_NAME).await?;
+    }
+    pg.create_database(DB_NAME).await?;
+    let restore = connect(port).await?;
+    pg_restore(&restore, &dump, progress).await?;
+    // dumps clear the search_path of their session, searches need a fresh one
+    drop(restore);
+    let client = connect(port).await?;
+    client.batch_execute(SEARCH_SETUP).await?;
+    // written last, so an interrupted import is repeated on the next start
+    client
+        .batch_execute("CREATE TABLE public.mu_import (checksum text NOT NULL);")
+        .await?;
+    client
+        .execute(
+            "INSERT INTO public.mu_import (checksum) VALUES ($1::text);",
+            &[&checksum],
+        )
+        .await?;
+    Ok((pg, client))
+}
 
-    pg.create_database("mangaupdates").await.unwrap();
+async fn connect(port: u16) -> Result<tokio_postgres::Client, ScrapeError> {
     let (client, connection) = tokio_postgres::connect(
-        "user=postgres password=password dbname=mangaupdates host=localhost port=5433",
+        &format!("user=postgres password=password dbname={DB_NAME} host=localhost port={port}"),
         NoTls,
     )
-    .await
-    .unwrap();
-
+    .await?;
     tokio::spawn(async move {
         if let Err(e) = connection.await {
-            eprintln!("connection error: {}", e);
-            panic!("mangaupdates db is not working")
+            log::error!("mangaupdates connection error: {}", e);
         }
-        drop(pg);
     });
-
-    pg_restore(&client, &path.join("external/mangaupdates.sql")).await;
     Ok(client)
 }
 
-async fn pg_restore(client: &tokio_postgres::Client, dump: &Path) {
-    let mut builder = vec![];
-    let mut queries = vec![];
-    let mut copies = vec![];
-    let mut copy = false;
-    for line in read_to_string(dump).unwrap().lines() {
+async fn imported_checksum(client: &tokio_postgres::Client) -> Result<Option<String>, ScrapeError> {
+    let exists: bool = client
+        .query_one("SELECT to_regclass('public.mu_import') IS NOT NULL;", &[])
+        .await?
+        .get(0);
+    if !exists {
+        return Ok(None);
+    }
+    let row = client
+        .query_opt("SELECT checksum FROM public.mu_import LIMIT 1;", &[])
+        .await?;
+    Ok(row.map(|v| v.get(0)))
+}
+
+/// Hashing the dump takes a while, so the hash is kept next to it
+/// and reused as long as size and modification time didn't change
+fn dump_checksum(dump: &Path) -> Result<String, ScrapeError> {
+    let meta = std::fs::metadata(dump)?;
+    let modified = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(ScrapeError::input_error)?
+        .as_nanos();
+    let key = format!("{} {}", meta.len(), modified);
+    let cache = dump.with_extension("sql.sha256");
+    if let Ok(cached) = std::fs::read_to_string(&cache) {
+        if let Some((cached_key, checksum)) = cached.trim().rsplit_once(' ') {
+            if cached_key == key {
+                return Ok(checksum.to_string());
+            }
+        }
+    }
+    let checksum = sha256(dump)?;
+    // only a cache, hashing again next time is fine
+    let _ = std::fs::write(&cache, format!("{key} {checksum}"));
+    Ok(checksum)
+}
+
+/// hex encoded
+fn sha256(file: &Path) -> Result<String, ScrapeError> {
+    let mut file = File::open(file)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(hasher.finish().iter().map(|v| format!("{v:02x}")).collect())
+}
+
+/// Reads the dump line by line, statements run as soon as they are complete
+/// and COPY data is streamed into the table
+async fn pg_restore(
+    client: &tokio_postgres::Client,
+    dump: &Path,
+    progress: Option<&ProgressFn>,
+) -> Result<(), ScrapeError> {
+    let file = tokio::fs::File::open(dump).await?;
+    let total = file.metadata().await?.len();
+    let report = |done: u64| {
+        if let Some(progress) = progress {
+            progress(ImportProgress { done, total })
+        }
+    };
+    let mut lines = BufReader::new(file).lines();
+    let mut done = 0;
+    let mut statement = String::new();
+    while let Some(line) = lines.next_line().await? {
+        done += line.len() as u64 + 1;
         if line.starts_with("--") || line.is_empty() {
             continue;
         }
-        builder.push(line.to_string());
+        statement.push_str(&line);
+        statement.push('\n');
         if line.ends_with("FROM stdin;") {
-            copy = true;
-        }
-        if copy {
-            if line == "\\." {
-                builder.pop();
-                let query = builder.join("\n");
-                copies.push(query);
-                builder = vec![];
-                copy = false;
-            }
-        } else {
-            if line.ends_with(";") && !line.ends_with("\\;") {
-                let query = builder.join("\n");
-                queries.push(query);
-                builder = vec![];
-            }
+            copy_in(client, &statement, &mut lines, &mut done, &report).await?;
+            statement.clear();
+        } else if line.ends_with(';') && !line.ends_with("\\;") {
+            client.batch_execute(&statement).await?;
+            statement.clear();
+            report(done);
         }
     }
-    client.batch_execute(&queries.join("\n")).await.unwrap();
-    for copy in copies {
-        if let Some((query, lines)) = copy.split_once("\n") {
-            let mut stream = stream::iter(
-                lines
-                    .split("\n")
-                    .map(|s| Bytes::from(format!("{s}\n")))
-                    .map(Ok::<_, tokio_postgres::Error>),
-            );
-            let sink: CopyInSink<Bytes> = client.copy_in(query).await.unwrap();
-            pin_mut!(sink);
-            sink.send_all(&mut stream).await.unwrap();
-            let _ = sink.finish().await.unwrap();
+    report(total);
+    Ok(())
+}
+
+/// Sends the lines up to `\.` to `query`
+async fn copy_in(
+    client: &tokio_postgres::Client,
+    query: &str,
+    lines: &mut Lines<BufReader<tokio::fs::File>>,
+    done: &mut u64,
+    report: &impl Fn(u64),
+) -> Result<(), ScrapeError> {
+    let sink: CopyInSink<Bytes> = client.copy_in(query).await?;
+    pin_mut!(sink);
+    let mut chunk = BytesMut::with_capacity(COPY_CHUNK);
+    while let Some(line) = lines.next_line().await? {
+        *done += line.len() as u64 + 1;
+        if line == "\\." {
+            break;
+        }
+        chunk.extend_from_slice(line.as_bytes());
+        chunk.extend_from_slice(b"\n");
+        if chunk.len() >= COPY_CHUNK {
+            // waits until postgres took the chunk
+            sink.send(chunk.split().freeze()).await?;
+            report(*done);
         }
     }
+    if !chunk.is_empty() {
+        sink.send(chunk.freeze()).await?;
+    }
+    sink.finish().await?;
+    report(*done);
+    Ok(())
 }
 
-// #[tokio::test]
-// async fn temp() {
-//     let client = init_postgres(&PathBuf::from("tests"), 5433).await.unwrap();
-//     sleep(Duration::from_secs(10)).await;
-//     let tags = client
-//         .query("SELECT * FROM public.tags;", &vec![])
-//         .await
-//         .unwrap();
-//     let mut tags = tags
-//         .into_iter()
-//         .map(|arr| arr.get("name"))
-//         .collect::<Vec<String>>();
-//     tags.sort();
-//     File::create("temp.txt")
-//         .unwrap()
-//         .write_all(tags.join("\n").as_bytes())
-//         .unwrap();
-// }
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct SearchRequest {
-    pub(crate) data: Array,
-    pub(crate) order: Order,
+    pub data: Array,
+    pub order: Order,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct FilterRequest {
-    filter: Vec<String>,
-    name: String,
-}
-impl Display for SearchRequest {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let query = match self.data.to_string() {
-            Some(v) => format!(" WHERE {}", v),
-            None => String::new(),
+impl SearchRequest {
+    /// Series with a title similar to `query`, an empty query matches everything
+    pub fn title(query: &str) -> Self {
+        let items = match query.trim().is_empty() {
+            true => vec![],
+            false => vec![ItemOrArray::Item(Item {
+                not: false,
+                data: ItemData::Title(query.trim().to_string()),
+            })],
+        };
+        Self {
+            data: Array { or: false, items },
+            order: Order {
+                desc: false,
+                kind: OrderKind::Relevance,
+            },
+        }
+    }
+
+    /// One page of `info` with the filters of `data`
+    pub fn query(&self, projection: Projection, limit: u32, offset: u32) -> Query {
+        let mut query = Query::default();
+        let filter = self.filter(&mut query);
+        let score = self.score(&mut query);
+        let order = self.order.to_sql(&mut query, score.as_deref());
+        let columns = match &score {
+            Some(score) => format!("{}, {score} AS score", projection.columns()),
+            None => projection.columns().to_string(),
         };
+        let limit = query.bind(limit as i64, "int8");
+        let offset = query.bind(offset as i64, "int8");
+        query.sql = format!(
+            "SELECT {columns} FROM info{filter} ORDER BY {order} LIMIT {limit} OFFSET {offset};"
+        );
+        query
+    }
 
-        let sql = format!("SELECT * FROM info{};", query);
-        write!(f, "{}", sql)
+    /// Best word similarity of the searched titles, None without a title filter
+    fn score(&self, query: &mut Query) -> Option<String> {
+        let mut parts = self
+            .data
+            .titles()
+            .into_iter()
+            .map(|v| {
+                format!(
+                    "word_similarity(mu_fold({}), mu_titles(titles))",
+                    query.bind(v.to_string(), "text")
+                )
+            })
+            .collect::<Vec<_>>();
+        match parts.len() {
+            0 => None,
+            1 => parts.pop(),
+            _ => Some(format!("GREATEST({})", parts.join(", "))),
+        }
+    }
+
+    /// Number of rows that match `data`
+    pub fn count(&self) -> Query {
+        let mut query = Query::default();
+        let filter = self.filter(&mut query);
+        query.sql = format!("SELECT count(*) FROM info{filter};");
+        query
+    }
+
+    fn filter(&self, query: &mut Query) -> String {
+        match self.data.to_sql(query) {
+            Some(v) => format!(" WHERE {v}"),
+            None => String::new(),
+        }
     }
 }
 
-fn save_sql_str(str: &str) -> String {
-    str.replace('\'', "''")
-        .replace('\\', "\\\\")
-        .replace('%', "\\%")
-        .replace('_', "\\_")
+/// Columns loaded by [`SearchRequest::query`]
+#[derive(Debug, Clone, Copy)]
+pub enum Projection {
+    /// every column, needed for a complete [`Record`]
+    Full,
+    /// enough for [`Record::to_result`]
+    Slim,
+}
+
+impl Projection {
+    fn columns(&self) -> &'static str {
+        match self {
+            Projection::Full => "*",
+            Projection::Slim => "id, public_id, url_key, titles, completed",
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
+pub struct FilterRequest {
+    filter: Vec<String>,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Order {
-    pub(crate) desc: bool,
-    pub(crate) kind: OrderKind,
+    pub desc: bool,
+    pub kind: OrderKind,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 pub enum OrderKind {
     Id,
     PrivateId,
     Title,
     LastUpdatedMU,
+    /// best title match first, `desc` reverses it
+    Relevance,
+    /// like `Relevance`, mixed with `bayesian_rating`. The value is the weight of the rating from 0 to 1
+    RelevanceRating(f32),
+}
+
+impl Order {
+    /// `id` is always the last key, so pages don't overlap when values are equal.
+    /// `score` is the similarity of [`SearchRequest::score`]
+    fn to_sql(&self, query: &mut Query, score: Option<&str>) -> String {
+        let score = score.unwrap_or("0::float4");
+        // the higher the score the better, so relevance is descending by default
+        let best_first = match self.desc {
+            true => "ASC",
+            false => "DESC",
+        };
+        let column = match self.kind {
+            OrderKind::Id => return format!("id {}", self.direction()),
+            OrderKind::PrivateId => "private_id",
+            OrderKind::Title => "lower(titles[1])",
+            OrderKind::LastUpdatedMU => "last_updated_mu",
+            OrderKind::Relevance => return format!("{score} {best_first}, id"),
+            OrderKind::RelevanceRating(weight) => {
+                let weight = query.bind(weight.clamp(0.0, 1.0), "float4");
+                return format!(
+                    "({score}) * (1 - {weight}) + coalesce(bayesian_rating, 0) / 10 * {weight} {best_first}, id"
+                );
+            }
+        };
+        format!("{column} {} NULLS LAST, id", self.direction())
+    }
+
+    fn direction(&self) -> &'static str {
+        match self.desc {
+            true => "DESC",
+            false => "ASC",
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
-pub(crate) enum ItemOrArray {
+pub enum ItemOrArray {
     Item(Item),
     Array(Array),
 }
 
 impl ItemOrArray {
-    fn to_string(&self) -> Option<String> {
+    fn to_sql(&self, query: &mut Query) -> Option<String> {
         match self {
-            ItemOrArray::Item(v) => Some(v.to_string()),
-            ItemOrArray::Array(v) => v.to_string(),
+            ItemOrArray::Item(v) => Some(v.to_sql(query)),
+            ItemOrArray::Array(v) => v.to_sql(query),
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Array {
-    pub(crate) or: bool,
-    pub(crate) items: Vec<ItemOrArray>,
+    pub or: bool,
+    pub items: Vec<ItemOrArray>,
 }
 
 impl Array {
-    fn to_string(&self) -> Option<String> {
-        let arr: Vec<_> = self.items.iter().filter_map(|v| v.to_string()).collect();
+    /// Titles that are searched for, negated ones are ignored
+    pub fn titles(&self) -> Vec<&str> {
+        let mut res = vec![];
+        for item in &self.items {
+            match item {
+                ItemOrArray::Item(Item {
+                    not: false,
+                    data: ItemData::Title(title),
+                }) => res.push(title.as_str()),
+                ItemOrArray::Array(array) => res.extend(array.titles()),
+                ItemOrArray::Item(_) => {}
+            }
+        }
+        res
+    }
+
+    /// None if there is nothing to filter
+    pub fn to_sql(&self, query: &mut Query) -> Option<String> {
+        let arr: Vec<_> = self.items.iter().filter_map(|v| v.to_sql(query)).collect();
         if arr.is_empty() {
             return None;
         }
         let v = arr.join(match self.or {
-            true => " or ",
-            false => " and ",
+            true => " OR ",
+            false => " AND ",
         });
         if arr.len() == 1 {
             return Some(v);
@@ -204,8 +664,8 @@ impl Array {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Item {
-    pub(crate) not: bool,
-    pub(crate) data: ItemData,
+    pub not: bool,
+    pub data: ItemData,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -237,112 +697,267 @@ pub enum IdOrValue {
     Id(i32),
 }
 
-impl Display for Item {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let n = match self.not {
-            true => "not ",
-            false => "",
-        };
-        let n2 = match self.not {
-            true => "!",
-            false => "",
-        };
-        let str = match &self.data {
-            ItemData::Id(id) => format!("id {}= {}", n2, id),
-            ItemData::Pid(id) => format!("private_id {}= {}", n2, id),
-            ItemData::PublicId(id) => format!("public_id {}= {}", n2, id),
-            ItemData::ForumId(id) => format!("forum_id {}= {}", n2, id),
-            ItemData::Key(key) => format!("url_key {}= {}", n2, key),
+impl Item {
+    pub fn to_sql(&self, query: &mut Query) -> String {
+        let sql = match &self.data {
+            ItemData::Id(id) => format!("id = {}", query.bind(*id, "int4")),
+            ItemData::Pid(id) => format!("private_id = {}", query.bind(*id, "int4")),
+            ItemData::PublicId(id) => format!("public_id = {}", query.bind(*id, "int8")),
+            ItemData::ForumId(id) => format!("forum_id = {}", query.bind(*id, "int8")),
+            ItemData::Key(key) => format!("url_key = {}", query.bind(key.clone(), "text")),
+            // accents and case are ignored, `<%` also matches titles with small spelling differences
             ItemData::Title(title) => format!(
-                "{}EXISTS(SELECT 1 FROM unnest(titles) as element WHERE lower(element) ILIKE '%{}%')",
-                n,
-                save_sql_str(&title.to_lowercase())
+                "(mu_titles(titles) LIKE mu_fold({}) OR mu_fold({}) <% mu_titles(titles))",
+                query.bind(contains_pattern(title), "text"),
+                query.bind(title.clone(), "text")
             ),
-            ItemData::Description(description) => {
-                format!("lower(description) {}ILIKE '%{}%'", n, save_sql_str(&description.to_lowercase()))
-            }
-            ItemData::Type(t) => match t {
-                IdOrValue::Value(q) => format!(
-                    "typ {}= (SELECT id FROM mtypes WHERE lower(name) = '{}' LIMIT 1)",
-                    n2,
-                    save_sql_str(&q.to_lowercase())
-                ),
-                IdOrValue::Id(id) => format!("typ {}= {}", n2, id),
-            },
-            ItemData::Year { eq, bigger, value } => {
-                format!("year {}", year(self.not, *bigger, *eq, value))
-            }
-            ItemData::LatestChapter { eq, bigger, value } => {
-                format!("latest_chapter {}", year(self.not, *bigger, *eq, value))
-            }
-            ItemData::Rating { eq, bigger, rating } => {
-                format!("bayesian_rating {}", year(self.not, *bigger, *eq, rating))
-            }
-            ItemData::Genre(v) => format!(
-                "{} = ANY(genres)",
-                match v {
-                    IdOrValue::Value(q) => format!(
-                        "(SELECT id FROM genres WHERE lower(name) = '{}')",
-                        q.to_lowercase()
-                    ),
-                    IdOrValue::Id(id) => id.to_string(),
-                }
+            ItemData::Description(description) => format!(
+                "mu_fold(description) LIKE mu_fold({})",
+                query.bind(contains_pattern(description), "text")
             ),
-            ItemData::Tag(v) => match v {
-                IdOrValue::Value(v) => format!(
-                    "tags && Array(SELECT id FROM tags WHERE lower(name) = '{}')",
-                    v.to_lowercase()
-                ),
-                IdOrValue::Id(id) => format!("{} = ANY(tags)", id),
-            },
-            ItemData::Licensed(l) => format!("licensed {}= {}", n2, l),
-            ItemData::Completed(c) => format!("completed {}= {}", n2, c),
-            ItemData::Author(a) => match a {
-                IdOrValue::Value(v) => format!(
-                    "author && Array(SELECT id FROM ppl WHERE lower(name) = '{}')",
-                    save_sql_str(&v.to_lowercase())
-                ),
-                IdOrValue::Id(id) => format!("{} = ANY(author)", id),
-            },
-            ItemData::Artist(a) => match a {
+            ItemData::Type(t) => match t {
                 IdOrValue::Value(v) => format!(
-                    "artist && Array(SELECT id FROM ppl WHERE lower(name) = '{}')",
-                    save_sql_str(&v.to_lowercase())
+                    "typ = (SELECT id FROM mtypes WHERE lower(name) = {} LIMIT 1)",
+                    query.bind(v.to_lowercase(), "text")
                 ),
-                IdOrValue::Id(id) => format!("{} = ANY(artist)", id),
+                IdOrValue::Id(id) => format!("typ = {}", query.bind(*id, "int4")),
             },
+            ItemData::Year { eq, bigger, value } => format!(
+                "year {} {}",
+                compare(*bigger, *eq),
+                query.bind(*value, "int4")
+            ),
+            ItemData::LatestChapter { eq, bigger, value } => format!(
+                "latest_chapter {} {}",
+                compare(*bigger, *eq),
+                query.bind(*value, "int4")
+            ),
+            ItemData::Rating { eq, bigger, rating } => format!(
+                "bayesian_rating {} {}",
+                compare(*bigger, *eq),
+                query.bind(*rating, "float4")
+            ),
+            ItemData::Genre(v) => any_of(query, "genres", "genres", v),
+            ItemData::Tag(v) => any_of(query, "tags", "tags", v),
+            ItemData::Licensed(l) => format!("licensed = {}", query.bind(*l, "bool")),
+            ItemData::Completed(c) => format!("completed = {}", query.bind(*c, "bool")),
+            ItemData::Author(v) => any_of(query, "author", "ppl", v),
+            ItemData::Artist(v) => any_of(query, "artist", "ppl", v),
             ItemData::Publisher { value, eng } => {
                 let field = match eng {
                     true => "publisher_english",
-                    false => "publisher_original"
+                    false => "publisher_original",
                 };
-                match value {
-                    IdOrValue::Value(v) => format!(
-                        "{} && Array(SELECT id FROM ppl WHERE lower(name) = '{}')",
-                        field,
-                        save_sql_str(&v.to_lowercase())
-                    ),
-                    IdOrValue::Id(id) => format!("{} = ANY({})", id, field)
-                }
+                any_of(query, field, "ppl", value)
             }
         };
-        write!(f, "{}", str)
+        match self.not {
+            true => format!("NOT ({sql})"),
+            false => sql,
+        }
     }
 }
 
-fn year(not: bool, mut bigger: bool, mut eq: bool, number: impl Display) -> String {
-    if not {
-        eq = !eq;
-        bigger = !bigger;
+/// `column` is an array of ids from `table`, names are matched case insensitive
+fn any_of(query: &mut Query, column: &str, table: &str, value: &IdOrValue) -> String {
+    match value {
+        IdOrValue::Value(v) => format!(
+            "{column} && ARRAY(SELECT id FROM {table} WHERE lower(name) = {})",
+            query.bind(v.to_lowercase(), "text")
+        ),
+        IdOrValue::Id(id) => format!("{} = ANY({column})", query.bind(*id, "int4")),
     }
+}
 
-    let eq = match eq {
-        true => "=",
-        false => "",
-    };
-    let sb = match bigger {
-        true => ">",
-        false => "<",
-    };
-    format!("{}{} {}", sb, eq, number)
+fn compare(bigger: bool, eq: bool) -> &'static str {
+    match (bigger, eq) {
+        (true, true) => ">=",
+        (true, false) => ">",
+        (false, true) => "<=",
+        (false, false) => "<",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    const HOSTILE: &[&str] = &["'; DROP TABLE info; --", "%", "_", "\\", "\\'; --"];
+
+    fn request(items: Vec<ItemData>) -> SearchRequest {
+        SearchRequest {
+            data: Array {
+                or: false,
+                items: items
+                    .into_iter()
+                    .map(|data| ItemOrArray::Item(Item { not: false, data }))
+                    .collect(),
+            },
+            order: Order {
+                desc: false,
+                kind: OrderKind::Relevance,
+            },
+        }
+    }
+
+    /// every value is a typed placeholder, nothing of the input is in the sql
+    fn assert_only_placeholders(query: &Query) {
+        assert!(!query.sql.contains('\''), "{}", query.sql);
+        assert!(!query.sql.contains("DROP"), "{}", query.sql);
+        assert!(!query.sql.contains("--"), "{}", query.sql);
+        let placeholder = Regex::new(r"\$(\d+)(::(text|int4|int8|float4|bool))?").unwrap();
+        for c in placeholder.captures_iter(&query.sql) {
+            assert!(c.get(2).is_some(), "untyped placeholder in {}", query.sql);
+            let n: usize = c[1].parse().unwrap();
+            assert!(n >= 1 && n <= query.params().len(), "{}", query.sql);
+        }
+    }
+
+    fn params(query: &Query) -> Vec<String> {
+        query.params().iter().map(|v| format!("{v:?}")).collect()
+    }
+
+    fn debug(s: &str) -> String {
+        format!("{s:?}")
+    }
+
+    #[tokio::test]
+    async fn started_only_after_the_import() {
+        let dataset = OfflineDataset::new("does-not-exist", 5433);
+        assert!(!dataset.is_started());
+        let _lock = dataset.db.lock().await;
+        assert!(!dataset.is_started());
+    }
+
+    #[test]
+    fn hostile_values_stay_parameters() {
+        for value in HOSTILE {
+            let req = request(vec![
+                ItemData::Key(value.to_string()),
+                ItemData::Title(value.to_string()),
+                ItemData::Description(value.to_string()),
+                ItemData::Genre(IdOrValue::Value(value.to_string())),
+                ItemData::Tag(IdOrValue::Value(value.to_string())),
+                ItemData::Type(IdOrValue::Value(value.to_string())),
+                ItemData::Author(IdOrValue::Value(value.to_string())),
+                ItemData::Publisher {
+                    value: IdOrValue::Value(value.to_string()),
+                    eng: true,
+                },
+            ]);
+            for query in [req.query(Projection::Slim, 50, 0), req.count()] {
+                assert_only_placeholders(&query);
+                let params = params(&query);
+                let lower = value.to_lowercase();
+                assert!(params.contains(&debug(value)), "{params:?}");
+                assert!(params.contains(&debug(&lower)), "{params:?}");
+                assert!(
+                    params.contains(&debug(&contains_pattern(value))),
+                    "{params:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn every_item_is_bound() {
+        let req = request(vec![
+            ItemData::Id(1),
+            ItemData::Pid(2),
+            ItemData::PublicId(3),
+            ItemData::ForumId(4),
+            ItemData::Year {
+                eq: true,
+                bigger: true,
+                value: 2015,
+            },
+            ItemData::LatestChapter {
+                eq: false,
+                bigger: false,
+                value: 10,
+            },
+            ItemData::Rating {
+                eq: false,
+                bigger: true,
+                rating: 7.5,
+            },
+            ItemData::Licensed(true),
+            ItemData::Completed(false),
+            ItemData::Artist(IdOrValue::Id(5)),
+            ItemData::Publisher {
+                value: IdOrValue::Id(6),
+                eng: false,
+            },
+        ]);
+        let query = req.count();
+        assert_only_placeholders(&query);
+        assert_eq!(query.params().len(), 11);
+        assert!(query.sql.contains("year >= $5::int4"), "{}", query.sql);
+        assert!(
+            query.sql.contains("bayesian_rating > $7::float4"),
+            "{}",
+            query.sql
+        );
+        assert!(
+            query.sql.contains("$10::int4 = ANY(artist)"),
+            "{}",
+            query.sql
+        );
+    }
+
+    #[test]
+    fn query_pages_and_orders() {
+        let mut req = request(vec![ItemData::Title("one piece".to_string())]);
+        let query = req.query(Projection::Slim, 50, 100);
+        assert_only_placeholders(&query);
+        assert!(query
+            .sql
+            .starts_with("SELECT id, public_id, url_key, titles, completed, "));
+        assert!(query.sql.contains("AS score"), "{}", query.sql);
+        assert!(
+            query.sql.ends_with("LIMIT $4::int8 OFFSET $5::int8;"),
+            "{}",
+            query.sql
+        );
+        assert_eq!(params(&query)[3..], ["50".to_string(), "100".to_string()]);
+
+        req.order = Order {
+            desc: true,
+            kind: OrderKind::LastUpdatedMU,
+        };
+        let query = req.query(Projection::Full, 10, 0);
+        assert!(query.sql.starts_with("SELECT *, "), "{}", query.sql);
+        assert!(
+            query
+                .sql
+                .contains("ORDER BY last_updated_mu DESC NULLS LAST, id"),
+            "{}",
+            query.sql
+        );
+
+        let empty = request(vec![]);
+        assert_eq!(empty.count().sql, "SELECT count(*) FROM info;");
+    }
+
+    #[test]
+    fn not_wraps_condition() {
+        let req = SearchRequest {
+            data: Array {
+                or: true,
+                items: vec![ItemOrArray::Item(Item {
+                    not: true,
+                    data: ItemData::Genre(IdOrValue::Id(3)),
+                })],
+            },
+            order: Order {
+                desc: false,
+                kind: OrderKind::Id,
+            },
+        };
+        assert_eq!(
+            req.count().sql,
+            "SELECT count(*) FROM info WHERE NOT ($1::int4 = ANY(genres));"
+        );
+    }
 }
@@ -0,0 +1,73 @@
+use tokio_postgres::types::ToSql;
+
+/// Extensions, functions and the index used by title searches, can be run on every start.
+/// `mu_titles` joins all titles, so the trigram index covers every title of a series.
+/// Everything is schema qualified, so it doesn't depend on the `search_path` of the session
+pub const SEARCH_SETUP: &str = "
+CREATE EXTENSION IF NOT EXISTS pg_trgm SCHEMA public;
+CREATE EXTENSION IF NOT EXISTS unaccent SCHEMA public;
+CREATE OR REPLACE FUNCTION public.mu_fold(text) RETURNS text
+    LANGUAGE sql IMMUTABLE PARALLEL SAFE STRICT
+    AS $$ SELECT pg_catalog.lower(public.unaccent('public.unaccent'::regdictionary, $1)) $$;
+CREATE OR REPLACE FUNCTION public.mu_titles(text[]) RETURNS text
+    LANGUAGE sql IMMUTABLE PARALLEL SAFE
+    AS $$ SELECT public.mu_fold(pg_catalog.array_to_string($1, ' | ')) $$;
+CREATE INDEX IF NOT EXISTS info_titles_trgm
+    ON public.info USING gin (public.mu_titles(titles) public.gin_trgm_ops);
+";
+
+/// Sql with `$n` placeholders and the values that belong to them.
+/// User input only ends up in `params`, never in `sql`
+#[derive(Debug, Default)]
+pub struct Query {
+    pub sql: String,
+    params: Vec<Box<dyn ToSql + Sync + Send>>,
+}
+
+impl Query {
+    /// Appends `value` to the parameters and returns its placeholder.
+    /// The placeholder is cast to `kind`, so postgres doesn't have to guess the type from the column
+    pub fn bind(&mut self, value: impl ToSql + Sync + Send + 'static, kind: &str) -> String {
+        self.params.push(Box::new(value));
+        format!("${}::{kind}", self.params.len())
+    }
+
+    pub fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params
+            .iter()
+            .map(|v| v.as_ref() as &(dyn ToSql + Sync))
+            .collect()
+    }
+}
+
+/// `%value%` for `LIKE` with `\`, `%` and `_` escaped
+pub fn contains_pattern(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contains_pattern_escapes() {
+        assert_eq!(contains_pattern("abc"), "%abc%");
+        assert_eq!(contains_pattern("100%"), "%100\\%%");
+        assert_eq!(contains_pattern("a_b"), "%a\\_b%");
+        assert_eq!(contains_pattern("a\\b"), "%a\\\\b%");
+        assert_eq!(contains_pattern("\\%_"), "%\\\\\\%\\_%");
+        assert_eq!(contains_pattern("'; --"), "%'; --%");
+    }
+
+    #[test]
+    fn bind_numbers_placeholders() {
+        let mut query = Query::default();
+        assert_eq!(query.bind(1, "int4"), "$1::int4");
+        assert_eq!(query.bind("a".to_string(), "text"), "$2::text");
+        assert_eq!(query.params().len(), 2);
+    }
+}
@@ -3,4 +3,7 @@ pub mod animeplanet;
 pub mod asuratoon;
 pub mod hidden;
 pub mod kitsu;
+pub mod mangadex;
 pub mod mangaupdates;
+pub mod myanimelist;
+pub mod tags;
@@ -0,0 +1,485 @@
+use crate::downloader::download;
+use crate::extractor::parser::clean_text;
+use crate::services::metadata::model::{MangaMetadata, Publisher, Relation, Status, Title};
+use crate::services::search::SearchPage;
+use crate::ScrapeError;
+use api_structure::scraper::ScrapeSearchResult;
+use regex::Regex;
+use reqwest::Client;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+const API: &str = "https://api.myanimelist.net/v2";
+const FIELDS: &str = "id,title,main_picture,alternative_titles,start_date,end_date,synopsis,mean,rank,popularity,num_list_users,nsfw,genres,media_type,status,num_volumes,num_chapters,authors{first_name,last_name},serialization{name},related_manga,related_anime";
+
+fn get_id(url: &str) -> Result<&str, ScrapeError> {
+    let mut id = url
+        .split_once("myanimelist.net/manga/")
+        .ok_or(ScrapeError::input_error("Invalid url"))?
+        .1;
+    if let Some((v, _)) = id.split_once(['/', '?']) {
+        id = v;
+    }
+    Ok(id)
+}
+
+/// Uses the v2 api when a client id is configured and falls back to the html page otherwise
+pub async fn get_data(
+    client: &Client,
+    url: &str,
+    client_id: Option<&str>,
+) -> Result<MangaMetadata, ScrapeError> {
+    let id = get_id(url)?;
+    match client_id {
+        Some(client_id) => get_data_api(client, id, client_id).await,
+        None => get_data_html(client, id).await,
+    }
+}
+
+async fn get_data_api(
+    client: &Client,
+    id: &str,
+    client_id: &str,
+) -> Result<MangaMetadata, ScrapeError> {
+    let url = format!("{API}/manga/{id}?fields={FIELDS}");
+    let resp = download(client.get(url).header("X-MAL-CLIENT-ID", client_id)).await?;
+    let manga: MangaResponse = serde_json::from_str(&resp)?;
+    let mut res = MangaMetadata {
+        cover: manga.main_picture.map(|v| v.large.unwrap_or(v.medium)),
+        description: manga.synopsis,
+        genres: manga.genres.into_iter().map(|v| v.name).collect(),
+        status: manga.status.as_deref().and_then(Status::parse),
+        kind: manga.media_type,
+        year: manga
+            .start_date
+            .as_ref()
+            .and_then(|v| v.split('-').next())
+            .and_then(|v| v.parse().ok()),
+        start_date: manga.start_date,
+        end_date: manga.end_date,
+        rating: manga.mean,
+        rank: manga.rank,
+        popularity: manga.popularity,
+        publishers: manga
+            .serialization
+            .into_iter()
+            .map(|v| Publisher {
+                name: v.node.name,
+                kind: Some("serialization".to_string()),
+            })
+            .collect(),
+        ..Default::default()
+    };
+    res.titles.push(Title::new(manga.title, Some("ja-ro")));
+    if let Some(titles) = manga.alternative_titles {
+        for (title, lang) in [(titles.en, "en"), (titles.ja, "ja")] {
+            if let Some(title) = title.filter(|v| !v.is_empty()) {
+                res.titles.push(Title::new(title, Some(lang)));
+            }
+        }
+        for synonym in titles.synonyms {
+            res.titles.push(Title::new(synonym, None));
+        }
+    }
+    for author in manga.authors {
+        let name = match author.node.first_name.is_empty() {
+            true => author.node.last_name,
+            false => format!("{} {}", author.node.first_name, author.node.last_name),
+        };
+        res.add_person(name, &author.role, Some(author.node.id.to_string()));
+    }
+    for (related, media_type) in [
+        (manga.related_manga, "MANGA"),
+        (manga.related_anime, "ANIME"),
+    ] {
+        for relation in related {
+            res.relations.push(Relation {
+                kind: relation.relation_type,
+                title: Some(relation.node.title),
+                media_type: Some(media_type.to_string()),
+                target: Some(("myanimelist".to_string(), relation.node.id.to_string())),
+            });
+        }
+    }
+    res.external_ids
+        .insert("myanimelist".to_string(), manga.id.to_string());
+    if let Some(members) = manga.num_list_users {
+        res.insert_extra("members", members);
+    }
+    if let Some(volumes) = manga.num_volumes.filter(|v| *v > 0) {
+        res.insert_extra("volumes", volumes);
+    }
+    if let Some(chapters) = manga.num_chapters.filter(|v| *v > 0) {
+        res.insert_extra("chapters", chapters);
+    }
+    if let Some(nsfw) = manga.nsfw {
+        res.insert_extra("nsfw", nsfw);
+    }
+    Ok(res)
+}
+
+async fn get_data_html(client: &Client, id: &str) -> Result<MangaMetadata, ScrapeError> {
+    let html = download(client.get(format!("https://myanimelist.net/manga/{id}"))).await?;
+    let doc = Html::parse_document(&html);
+    let select = |s: &str| doc.select(&Selector::parse(s).unwrap()).next();
+    let mut res = MangaMetadata::default();
+    let title = select("span[itemprop=name]").ok_or(ScrapeError::node_not_found())?;
+    // the english title is part of the same span
+    let title = title.text().next().map(|v| clean_text(v.to_string()));
+    if let Some(title) = title {
+        res.titles.push(Title::new(title, Some("ja-ro")));
+    }
+    res.cover = select("img[itemprop=image]")
+        .and_then(|v| v.attr("data-src").or(v.attr("src")))
+        .map(|v| v.to_string());
+    res.description =
+        select("span[itemprop=description]").map(|v| clean_text(v.text().collect::<String>()));
+    res.rating = select("span[itemprop=ratingValue]")
+        .and_then(|v| v.text().collect::<String>().trim().parse().ok());
+    let p = patterns();
+    let pads = Selector::parse("div.leftside div.spaceit_pad").unwrap();
+    let links = Selector::parse("a").unwrap();
+    for pad in doc.select(&pads) {
+        let text = clean_text(pad.text().collect::<String>());
+        let (label, value) = match text.split_once(':') {
+            Some((label, value)) => (label, clean_text(value.to_string())),
+            None => continue,
+        };
+        let link_texts = |v: &ElementRef| -> Vec<String> {
+            v.select(&links)
+                .map(|v| v.text().collect::<String>())
+                .collect()
+        };
+        match label {
+            "Synonyms" => {
+                for synonym in value.split(", ") {
+                    res.titles.push(Title::new(synonym, None));
+                }
+            }
+            "Japanese" => res.titles.push(Title::new(value, Some("ja"))),
+            "English" => res.titles.push(Title::new(value, Some("en"))),
+            "Type" => res.kind = Some(value),
+            "Status" => res.status = Status::parse(&value),
+            "Published" => {
+                let (start, end) = value.split_once(" to ").unwrap_or((&value, "?"));
+                res.year = start.rsplit(' ').next().and_then(|v| v.parse().ok());
+                res.start_date = Some(start.to_string());
+                if end != "?" {
+                    res.end_date = Some(end.to_string());
+                }
+            }
+            "Genres" | "Genre" | "Themes" | "Theme" | "Demographic" => {
+                res.genres.append(&mut link_texts(&pad))
+            }
+            "Serialization" => {
+                for name in link_texts(&pad) {
+                    res.publishers.push(Publisher {
+                        name,
+                        kind: Some("serialization".to_string()),
+                    })
+                }
+            }
+            "Authors" => {
+                for cap in p.authors.captures_iter(&pad.html()) {
+                    res.add_person(&cap[2], &cap[3], Some(cap[1].to_string()));
+                }
+            }
+            "Ranked" => res.rank = parse_rank(&value),
+            "Popularity" => res.popularity = parse_rank(&value),
+            "Members" | "Favorites" | "Volumes" | "Chapters" => {
+                res.insert_extra(&label.to_lowercase(), value.replace(',', ""));
+            }
+            _ => {}
+        }
+    }
+    let entries = Selector::parse("div.related-entries div.entry").unwrap();
+    let relation = Selector::parse("div.relation").unwrap();
+    let title = Selector::parse("div.title a").unwrap();
+    for entry in doc.select(&entries) {
+        let kind = entry.select(&relation).next();
+        let link = entry.select(&title).next();
+        if let (Some(kind), Some(link)) = (kind, link) {
+            let href = link.attr("href").unwrap_or_default();
+            let target = p
+                .related
+                .captures(href)
+                .map(|v| (v[1].to_uppercase(), v[2].to_string()));
+            res.relations.push(Relation {
+                kind: clean_text(kind.text().collect::<String>()),
+                title: Some(clean_text(link.text().collect::<String>())),
+                media_type: target.as_ref().map(|v| v.0.clone()),
+                target: target.map(|v| ("myanimelist".to_string(), v.1)),
+            });
+        }
+    }
+    res.external_ids
+        .insert("myanimelist".to_string(), id.to_string());
+    Ok(res)
+}
+
+struct Patterns {
+    authors: Regex,
+    related: Regex,
+}
+
+fn patterns() -> &'static Patterns {
+    static PATTERNS: OnceLock<Patterns> = OnceLock::new();
+    PATTERNS.get_or_init(|| Patterns {
+        authors: Regex::new(r#"<a href="[^"]*/people/(\d+)/[^"]*">([^<]+)</a>\s*\(([^)]+)\)"#)
+            .unwrap(),
+        related: Regex::new(r"myanimelist\.net/(manga|anime)/(\d+)").unwrap(),
+    })
+}
+
+/// `#1234` => 1234
+fn parse_rank(s: &str) -> Option<u64> {
+    s.trim_start_matches('#')
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+pub async fn search(
+    client: &Client,
+    query: String,
+    page: u32,
+    client_id: Option<&str>,
+) -> Result<SearchPage, ScrapeError> {
+    let limit = 50;
+    let offset = (page.max(1) - 1) * limit;
+    match client_id {
+        Some(client_id) => {
+            let url = api_search_url(&query, limit, offset);
+            let resp = download(client.get(url).header("X-MAL-CLIENT-ID", client_id)).await?;
+            let data: SearchResponse = serde_json::from_str(&resp)?;
+            let results = data
+                .data
+                .into_iter()
+                .map(|v| ScrapeSearchResult {
+                    title: v.node.title,
+                    url: format!("https://myanimelist.net/manga/{}", v.node.id),
+                    cover: v
+                        .node
+                        .main_picture
+                        .map(|v| v.large.unwrap_or(v.medium))
+                        .unwrap_or_default(),
+                    r#type: v.node.media_type,
+                    status: v.node.status,
+                })
+                .collect();
+            Ok(SearchPage {
+                results,
+                page,
+                has_next: data.paging.next.is_some(),
+                total: None,
+                external_ids: Default::default(),
+            })
+        }
+        None => {
+            let url = format!(
+                "https://myanimelist.net/manga.php?q={}&cat=manga&show={offset}",
+                urlencoding::encode(&query)
+            );
+            let html = download(client.get(url)).await?;
+            let doc = Html::parse_document(&html);
+            let rows = Selector::parse("div.js-categories-seasonal tr").unwrap();
+            let link = Selector::parse("a.hoverinfo_trigger").unwrap();
+            let title = Selector::parse("strong").unwrap();
+            let cover = Selector::parse("img").unwrap();
+            let kind = Selector::parse("td:nth-child(3)").unwrap();
+            let mut res = vec![];
+            for row in doc.select(&rows) {
+                let (link, title) = match (row.select(&link).next(), row.select(&title).next()) {
+                    (Some(link), Some(title)) => (link, title),
+                    _ => continue,
+                };
+                res.push(ScrapeSearchResult {
+                    title: title.text().collect(),
+                    url: link.attr("href").unwrap_or_default().to_string(),
+                    cover: row
+                        .select(&cover)
+                        .next()
+                        .and_then(|v| v.attr("data-src").or(v.attr("src")))
+                        .unwrap_or_default()
+                        .to_string(),
+                    r#type: row
+                        .select(&kind)
+                        .next()
+                        .map(|v| clean_text(v.text().collect::<String>())),
+                    status: None,
+                })
+            }
+            // the html search shows 50 rows per page and has no page count
+            Ok(SearchPage {
+                has_next: res.len() as u32 >= limit,
+                results: res,
+                page,
+                total: None,
+                external_ids: Default::default(),
+            })
+        }
+    }
+}
+
+/// The api rejects queries with less than 3 characters, they show the ranking instead
+fn api_search_url(query: &str, limit: u32, offset: u32) -> String {
+    let query = query.trim();
+    match query.chars().count() < 3 {
+        true => format!("{API}/manga/ranking?ranking_type=all&limit={limit}&offset={offset}&fields=media_type,status"),
+        false => format!(
+            "{API}/manga?q={}&limit={limit}&offset={offset}&fields=media_type,status",
+            urlencoding::encode(query)
+        ),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Picture {
+    pub medium: String,
+    pub large: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AlternativeTitles {
+    #[serde(default)]
+    pub synonyms: Vec<String>,
+    pub en: Option<String>,
+    pub ja: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Genre {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AuthorNode {
+    pub id: i64,
+    #[serde(default)]
+    pub first_name: String,
+    #[serde(default)]
+    pub last_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Author {
+    pub node: AuthorNode,
+    pub role: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializationNode {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Serialization {
+    pub node: SerializationNode,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RelatedNode {
+    pub id: i64,
+    pub title: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Related {
+    pub node: RelatedNode,
+    pub relation_type: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MangaResponse {
+    pub id: i64,
+    pub title: String,
+    pub main_picture: Option<Picture>,
+    pub alternative_titles: Option<AlternativeTitles>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub synopsis: Option<String>,
+    pub mean: Option<f64>,
+    pub rank: Option<u64>,
+    pub popularity: Option<u64>,
+    pub num_list_users: Option<u64>,
+    pub nsfw: Option<String>,
+    #[serde(default)]
+    pub genres: Vec<Genre>,
+    pub media_type: Option<String>,
+    pub status: Option<String>,
+    pub num_volumes: Option<u64>,
+    pub num_chapters: Option<u64>,
+    #[serde(default)]
+    pub authors: Vec<Author>,
+    #[serde(default)]
+    pub serialization: Vec<Serialization>,
+    #[serde(default)]
+    pub related_manga: Vec<Related>,
+    #[serde(default)]
+    pub related_anime: Vec<Related>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SearchNode {
+    pub id: i64,
+    pub title: String,
+    pub main_picture: Option<Picture>,
+    pub media_type: Option<String>,
+    pub status: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SearchItem {
+    pub node: SearchNode,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SearchResponse {
+    pub data: Vec<SearchItem>,
+    #[serde(default)]
+    pub paging: Paging,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Paging {
+    pub next: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_queries_show_the_ranking() {
+        for query in ["", "a", " ab ", "進撃"] {
+            assert!(
+                api_search_url(query, 50, 0).contains("/manga/ranking?"),
+                "{query}"
+            );
+        }
+        assert_eq!(
+            api_search_url(" one piece", 50, 50),
+            format!("{API}/manga?q=one%20piece&limit=50&offset=50&fields=media_type,status")
+        );
+        assert!(api_search_url("進撃の", 50, 0).contains("/manga?q="));
+    }
+
+    #[test]
+    fn html_patterns() {
+        let p = patterns();
+        let authors = r#"<a href="/people/1868/Eiichiro_Oda">Oda, Eiichiro</a> (Story &amp; Art)"#;
+        let cap = p.authors.captures(authors).unwrap();
+        assert_eq!(
+            (&cap[1], &cap[2], &cap[3]),
+            ("1868", "Oda, Eiichiro", "Story &amp; Art")
+        );
+        let cap = p
+            .related
+            .captures("https://myanimelist.net/anime/21/One_Piece")
+            .unwrap();
+        assert_eq!((&cap[1], &cap[2]), ("anime", "21"));
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagSource {
+    Anilist,
+    Kitsu,
+    MangaUpdates,
+    AnimePlanet,
+}
+
+impl TagSource {
+    pub fn from_uri(uri: &str) -> Option<Self> {
+        match uri {
+            "anilist" => Some(Self::Anilist),
+            "kitsu" => Some(Self::Kitsu),
+            "manga-updates" => Some(Self::MangaUpdates),
+            "anime-planet" => Some(Self::AnimePlanet),
+            _ => None,
+        }
+    }
+}
+
+pub struct CanonicalTag {
+    pub name: &'static str,
+    anilist: &'static str,
+    kitsu: &'static str,
+    mangaupdates: &'static str,
+    animeplanet: &'static str,
+    /// other spellings used by sites without an entry
+    pub aliases: &'static [&'static str],
+}
+
+impl CanonicalTag {
+    /// Name the site uses for this tag
+    pub fn get(&self, source: TagSource) -> Option<&'static str> {
+        let v = match source {
+            TagSource::Anilist => self.anilist,
+            TagSource::Kitsu => self.kitsu,
+            TagSource::MangaUpdates => self.mangaupdates,
+            TagSource::AnimePlanet => self.animeplanet,
+        };
+        match v.is_empty() {
+            true => None,
+            false => Some(v),
+        }
+    }
+}
+
+const fn tag(
+    name: &'static str,
+    anilist: &'static str,
+    kitsu: &'static str,
+    mangaupdates: &'static str,
+    animeplanet: &'static str,
+    aliases: &'static [&'static str],
+) -> CanonicalTag {
+    CanonicalTag {
+        name,
+        anilist,
+        kitsu,
+        mangaupdates,
+        animeplanet,
+        aliases,
+    }
+}
+
+/// name, anilist, kitsu(category slug), mangaupdates(genre/category), anime-planet, aliases
+/// empty strings mean the site has no equivalent
+#[rustfmt::skip]
+pub const CANONICAL_TAGS: [CanonicalTag; 62] = [
+    tag("4-Koma", "4-koma", "4-koma", "4-Koma", "4-koma", &["Yonkoma"]),
+    tag("Action", "Action", "action", "Action", "Action", &[]),
+    tag("Adventure", "Adventure", "adventure", "Adventure", "Adventure", &[]),
+    tag("Aliens", "Aliens", "aliens", "Alien/s", "Aliens", &["Alien"]),
+    tag("Boys' Love", "Boys' Love", "shounen-ai", "Shounen Ai", "BL", &["Shonen Ai", "Boys Love"]),
+    tag("Comedy", "Comedy", "comedy", "Comedy", "Comedy", &[]),
+    tag("Cooking", "Cooking", "cooking", "Cooking", "Cooking", &["Gourmet", "Food"]),
+    tag("Crime", "Crime", "crime", "Crime", "Crime", &[]),
+    tag("Crossdressing", "Crossdressing", "cross-dressing", "Crossdressing", "Crossdressing", &["Cross-Dressing"]),
+    tag("Cultivation", "Cultivation", "", "Cultivation", "Cultivation", &[]),
+    tag("Cyberpunk", "Cyberpunk", "cyberpunk", "Cyberpunk", "Cyberpunk", &[]),
+    tag("Delinquents", "Delinquents", "delinquents", "Delinquent/s", "Delinquents", &["Delinquent"]),
+    tag("Demons", "Demons", "demon", "Demon/s", "Demons", &["Demon"]),
+    tag("Drama", "Drama", "drama", "Drama", "Drama", &[]),
+    tag("Dungeon", "Dungeon", "", "Dungeon/s", "Dungeon", &["Dungeons"]),
+    tag("Ecchi", "Ecchi", "ecchi", "Ecchi", "Ecchi", &["Fan Service"]),
+    tag("Fantasy", "Fantasy", "fantasy", "Fantasy", "Fantasy", &[]),
+    tag("Full Color", "Full Color", "", "Full Color", "Full Color", &["Full Colour", "Colored"]),
+    tag("Gender Bender", "Gender Bending", "gender-bender", "Gender Bender", "Gender Bender", &["Genderswap", "Magical Sex Shift"]),
+    tag("Ghosts", "Ghost", "ghost", "Ghost/s", "Ghosts", &["Ghost"]),
+    tag("Girls' Love", "Yuri", "shoujo-ai", "Shoujo Ai", "GL", &["Shojo Ai", "Girls Love"]),
+    tag("Harem", "Female Harem", "harem", "Harem", "Harem", &[]),
+    tag("Historical", "Historical", "historical", "Historical", "Historical", &["History"]),
+    tag("Horror", "Horror", "horror", "Horror", "Horror", &[]),
+    tag("Isekai", "Isekai", "isekai", "Isekai", "Isekai", &["Transported to Another World", "Another World"]),
+    tag("Josei", "Josei", "josei", "Josei", "Josei", &[]),
+    tag("Magic", "Magic", "magic", "Magic", "Magic", &[]),
+    tag("Magical Girl", "Mahou Shoujo", "magical-girl", "Magical Girl/s", "Magical Girl", &["Mahou Shojo"]),
+    tag("Martial Arts", "Martial Arts", "martial-arts", "Martial Arts", "Martial Arts", &[]),
+    tag("Mecha", "Mecha", "mecha", "Mecha", "Mecha", &[]),
+    tag("Medical", "Medicine", "medical", "Medical", "Medical", &["Medicine"]),
+    tag("Military", "Military", "military", "Military", "Military", &[]),
+    tag("Music", "Music", "music", "Music", "Music", &[]),
+    tag("Mystery", "Mystery", "mystery", "Mystery", "Mystery", &[]),
+    tag("Ninja", "Ninja", "ninja", "Ninja/s", "Ninja", &[]),
+    tag("Oneshot", "", "", "Oneshot", "One Shot", &["One-Shot"]),
+    tag("Post-Apocalyptic", "Post-Apocalyptic", "post-apocalypse", "Post-Apocalyptic", "Post-apocalyptic", &["Apocalypse"]),
+    tag("Psychological", "Psychological", "psychological", "Psychological", "Psychological", &[]),
+    tag("Reincarnation", "Reincarnation", "reincarnation", "Reincarnation", "Reincarnation", &["Rebirth"]),
+    tag("Reverse Harem", "Male Harem", "reverse-harem", "Reverse Harem", "Reverse Harem", &[]),
+    tag("Romance", "Romance", "romance", "Romance", "Romance", &[]),
+    tag("Samurai", "Samurai", "samurai", "Samurai", "Samurai", &[]),
+    tag("School Life", "School", "school", "School Life", "School Life", &["School"]),
+    tag("Sci-Fi", "Sci-Fi", "science-fiction", "Sci-fi", "Sci Fi", &["Science Fiction", "SF"]),
+    tag("Seinen", "Seinen", "seinen", "Seinen", "Seinen", &[]),
+    tag("Shoujo", "Shoujo", "shoujo", "Shoujo", "Shoujo", &["Shojo"]),
+    tag("Shounen", "Shounen", "shounen", "Shounen", "Shounen", &["Shonen"]),
+    tag("Slice of Life", "Slice of Life", "slice-of-life", "Slice of Life", "Slice of Life", &[]),
+    tag("Smut", "", "", "Smut", "Smut", &[]),
+    tag("Space", "Space", "space", "Space", "Outer Space", &["Outer Space"]),
+    tag("Sports", "Sports", "sports", "Sports", "Sports", &["Sport"]),
+    tag("Super Power", "Super Power", "super-power", "Super Power/s", "Superpowers", &["Superpowers"]),
+    tag("Supernatural", "Supernatural", "supernatural", "Supernatural", "Supernatural", &[]),
+    tag("Survival", "Survival", "survival", "Survival", "Survival", &[]),
+    tag("Thriller", "Thriller", "thriller", "Thriller", "Thriller", &["Suspense"]),
+    tag("Time Travel", "Time Manipulation", "time-travel", "Time Travel", "Time Travel", &[]),
+    tag("Tragedy", "Tragedy", "tragedy", "Tragedy", "", &[]),
+    tag("Vampires", "Vampire", "vampire", "Vampire/s", "Vampires", &["Vampire"]),
+    tag("Video Games", "Video Games", "video-game", "Video Game/s", "", &["Video Game"]),
+    tag("Yaoi", "", "yaoi", "Yaoi", "Yaoi", &[]),
+    tag("Yuri", "Yuri", "yuri", "Yuri", "Yuri", &[]),
+    tag("Zombies", "Zombie", "zombie", "Zombie/s", "Zombies", &["Zombie"]),
+];
+
+/// "Sci Fi", "sci-fi" and "SciFi" share the same key
+fn key(tag: &str) -> String {
+    tag.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+fn lookup() -> &'static HashMap<String, usize> {
+    static LOOKUP: OnceLock<HashMap<String, usize>> = OnceLock::new();
+    LOOKUP.get_or_init(|| {
+        let mut map = HashMap::new();
+        // canonical names win over the site specific names
+        for (i, tag) in CANONICAL_TAGS.iter().enumerate() {
+            map.insert(key(tag.name), i);
+        }
+        for (i, tag) in CANONICAL_TAGS.iter().enumerate() {
+            let names = [tag.anilist, tag.kitsu, tag.mangaupdates, tag.animeplanet];
+            for name in names.iter().chain(tag.aliases).filter(|v| !v.is_empty()) {
+                map.entry(key(name)).or_insert(i);
+            }
+        }
+        map
+    })
+}
+
+pub fn canonical(tag: &str) -> Option<&'static CanonicalTag> {
+    lookup().get(&key(tag)).map(|i| &CANONICAL_TAGS[*i])
+}
+
+/// Unknown tags are kept as they are
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut res: Vec<String> = vec![];
+    for tag in tags {
+        let tag = match canonical(&tag) {
+            Some(v) => v.name.to_string(),
+            None => tag.trim().to_string(),
+        };
+        if !res.iter().any(|v| key(v) == key(&tag)) {
+            res.push(tag);
+        }
+    }
+    res
+}
+
+/// Translates tags into the vocabulary of a site.
+/// Tags without a mapping are passed through, so site specific names keep working.
+pub fn translate_tags(tags: &[String], source: TagSource) -> Vec<String> {
+    tags.iter().map(|tag| translate_tag(tag, source)).collect()
+}
+
+pub fn translate_tag(tag: &str, source: TagSource) -> String {
+    canonical(tag)
+        .and_then(|v| v.get(source))
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| tag.to_string())
+}
+
+/// Translates tags into the names of `vocabulary`, for sites that list their own tags
+/// like mangadex or `.search` files. Tags without an equivalent are passed through
+pub fn translate_tags_to(tags: &[String], vocabulary: &[&str]) -> Vec<String> {
+    tags.iter()
+        .map(|tag| {
+            let same = |name: &&&str| key(name) == key(tag);
+            let canonical_name = canonical(tag).map(|v| v.name);
+            let equivalent = |name: &&&str| {
+                canonical_name.is_some() && canonical(name).map(|v| v.name) == canonical_name
+            };
+            vocabulary
+                .iter()
+                .find(same)
+                .or_else(|| vocabulary.iter().find(equivalent))
+                .map(|v| v.to_string())
+                .unwrap_or_else(|| tag.clone())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn translate_to_sources() {
+        let input = tags(&["Sci Fi", "BL", "Alien", "Unknown Tag"]);
+        assert_eq!(
+            translate_tags(&input, TagSource::MangaUpdates),
+            ["Sci-fi", "Shounen Ai", "Alien/s", "Unknown Tag"]
+        );
+        assert_eq!(
+            translate_tags(&input, TagSource::Kitsu),
+            ["science-fiction", "shounen-ai", "aliens", "Unknown Tag"]
+        );
+        // anime-planet has no tag for games in general
+        assert_eq!(
+            translate_tags(&tags(&["Video Games"]), TagSource::AnimePlanet),
+            ["Video Games"]
+        );
+    }
+
+    #[test]
+    fn translate_to_vocabulary() {
+        let vocabulary = [
+            "Boys' Love",
+            "Genderswap",
+            "Magical Girls",
+            "Sci-Fi",
+            "Long Strip",
+        ];
+        assert_eq!(
+            translate_tags_to(
+                &tags(&[
+                    "shounen ai",
+                    "Gender Bender",
+                    "Mahou Shoujo",
+                    "scifi",
+                    "long strip",
+                    "Gore"
+                ]),
+                &vocabulary
+            ),
+            [
+                "Boys' Love",
+                "Genderswap",
+                "Magical Girls",
+                "Sci-Fi",
+                "Long Strip",
+                "Gore"
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_myanimelist_genres() {
+        assert_eq!(
+            normalize_tags(tags(&[
+                "Gourmet",
+                "Magical Sex Shift",
+                "Suspense",
+                "School"
+            ])),
+            ["Cooking", "Gender Bender", "Thriller", "School Life"]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::downloader::download;
+use crate::extractor::date::{self, parse_date};
+use crate::extractor::episode::Episode;
+use crate::extractor::feed::{parse_feed, FeedItem};
+use crate::services::config_to_request_builder;
+use crate::services::multisite::{DuplicatePolicy, Info};
+use crate::ScrapeError;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// Content of a `.feed` file
+#[derive(Deserialize)]
+pub struct FeedDeserialized {
+    /// feed url, `{url}` is replaced with the series url. defaults to the series url
+    feed: Option<String>,
+    /// items whose title doesn't match are skipped
+    title_filter: Option<String>,
+    language: Option<String>,
+    /// group of every item, otherwise read from `[Group]` in the item title
+    group: Option<String>,
+    request_config: Option<String>,
+    #[serde(default)]
+    duplicates: DuplicatePolicy,
+}
+
+impl FeedDeserialized {
+    pub(crate) fn convert(self, folder: &Path) -> Result<FeedSource, ScrapeError> {
+        let config = match self.request_config {
+            Some(file) => serde_json::from_str(&read_to_string(folder.join(file))?)?,
+            None => HashMap::new(),
+        };
+        let title_filter = match self.title_filter {
+            Some(v) => Some(regex::Regex::new(&v).map_err(ScrapeError::input_error)?),
+            None => None,
+        };
+        Ok(FeedSource {
+            feed: self.feed,
+            title_filter,
+            language: self.language,
+            group: self.group,
+            config,
+            duplicates: self.duplicates,
+        })
+    }
+}
+
+pub struct FeedSource {
+    feed: Option<String>,
+    title_filter: Option<regex::Regex>,
+    language: Option<String>,
+    group: Option<String>,
+    config: HashMap<String, String>,
+    pub(crate) duplicates: DuplicatePolicy,
+}
+
+impl FeedSource {
+    pub(crate) async fn get_chapters(
+        &self,
+        client: &Client,
+        uri: &str,
+        url: &str,
+    ) -> Result<Vec<Info>, ScrapeError> {
+        let feed = match &self.feed {
+            Some(v) => v.replace("{url}", url.trim_end_matches('/')),
+            None => url.to_string(),
+        };
+        let xml = download(config_to_request_builder(client, &self.config, &feed)).await?;
+        Ok(parse_feed(&xml)
+            .into_iter()
+            .filter(|v| match &self.title_filter {
+                Some(filter) => filter.is_match(&v.title),
+                None => true,
+            })
+            .map(|v| {
+                let mut info = to_info(uri, v);
+                info.language = self.language.clone();
+                if let Some(group) = &self.group {
+                    info.groups = vec![group.clone()];
+                }
+                info
+            })
+            .collect())
+    }
+}
+
+/// Items without a link use their id, feeds often use the chapter url as id
+pub(crate) fn to_info(uri: &str, item: FeedItem) -> Info {
+    let now = date::now();
+    let (group, rest) = split_group(&item.title);
+    let episode = Episode::parse(rest);
+    Info {
+        site: uri.to_string(),
+        url: item.link.or(item.id).unwrap_or_default(),
+        episode: episode.chapter.unwrap_or(0.0),
+        unparsed: episode.chapter.is_none(),
+        volume: episode.volume,
+        release_at: item
+            .published
+            .as_deref()
+            .and_then(|v| parse_date(v, now))
+            .filter(|v| *v > now),
+        published_at: item.published,
+        groups: group.into_iter().collect(),
+        titles: vec![item.title],
+        ..Default::default()
+    }
+}
+
+/// `[Group] Title c.5` or `Title c.5 [Group]`, mangaupdates lists a release once per group
+fn split_group(title: &str) -> (Option<String>, &str) {
+    let trimmed = title.trim();
+    if let Some(rest) = trimmed.strip_prefix('[') {
+        if let Some((group, rest)) = rest.split_once(']') {
+            return (non_empty(group), rest);
+        }
+    }
+    if let Some(rest) = trimmed.strip_suffix(']') {
+        if let Some((rest, group)) = rest.rsplit_once('[') {
+            return (non_empty(group), rest);
+        }
+    }
+    (None, trimmed)
+}
+
+fn non_empty(group: &str) -> Option<String> {
+    Some(group.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(title: &str) -> FeedItem {
+        FeedItem {
+            title: title.to_string(),
+            link: Some("https://example.com/1".to_string()),
+            published: None,
+            id: None,
+        }
+    }
+
+    #[test]
+    fn groups_from_titles() {
+        let info = to_info("manga-updates", item("[Lilyna Scans] Martial Peak c.3000"));
+        assert_eq!(info.groups, ["Lilyna Scans"]);
+        assert_eq!(info.episode, 3000.0);
+        let info = to_info("manga-updates", item("Martial Peak c.3000 [Other Group]"));
+        assert_eq!(info.groups, ["Other Group"]);
+        // the number in the group name isn't the chapter
+        let info = to_info("manga-updates", item("[Scans 4 You] 12"));
+        assert_eq!((info.groups.len(), info.episode), (1, 12.0));
+        let info = to_info("manga-updates", item("Martial Peak c.3000"));
+        assert!(info.groups.is_empty());
+        assert!(!info.unparsed);
+    }
+}
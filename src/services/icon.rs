@@ -45,7 +45,7 @@ impl ExternalSite {
                                 )?,
                             );
                         }
-                        "scraper" | "search" | "metadata" => {}
+                        "scraper" | "search" | "metadata" | "feed" => {}
                         _ => {
                             files.insert(name.to_string(), path);
                         }
@@ -0,0 +1,346 @@
+use crate::error::ScrapeError;
+use crate::services::metadata::model::{MangaMetadata, Person, Publisher, Relation, Status, Title};
+use crate::services::metadata::ItemOrArray;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Value and the uris of every source that supplied it
+#[derive(Debug, Clone, Serialize)]
+pub struct Sourced<T> {
+    pub value: T,
+    pub sources: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MergedMetadata {
+    pub titles: Vec<Sourced<Title>>,
+    pub cover: Option<Sourced<String>>,
+    pub banner: Option<Sourced<String>>,
+    pub description: Option<Sourced<String>>,
+    pub people: Vec<Sourced<Person>>,
+    pub tags: Vec<Sourced<String>>,
+    pub genres: Vec<Sourced<String>>,
+    pub status: Option<Sourced<Status>>,
+    pub kind: Option<Sourced<String>>,
+    pub start_date: Option<Sourced<String>>,
+    pub end_date: Option<Sourced<String>>,
+    pub year: Option<Sourced<i32>>,
+    pub publishers: Vec<Sourced<Publisher>>,
+    pub relations: Vec<Sourced<Relation>>,
+    pub rating: Option<Sourced<f64>>,
+    pub rank: Option<Sourced<u64>>,
+    pub popularity: Option<Sourced<u64>>,
+    pub latest_chapter: Option<Sourced<f64>>,
+    pub external_ids: HashMap<String, Sourced<String>>,
+    pub extra: HashMap<String, Sourced<ItemOrArray>>,
+    /// url => error of the sources that couldnt be fetched
+    #[serde(skip)]
+    pub failed: HashMap<String, ScrapeError>,
+}
+
+/// `items` are (uri, metadata) pairs.
+/// Single values are taken from the first uri in `priority` that has them,
+/// uris missing in `priority` are ranked after it in their original order.
+pub fn merge(mut items: Vec<(String, MangaMetadata)>, priority: &[String]) -> MergedMetadata {
+    items.sort_by_key(|(uri, _)| {
+        priority
+            .iter()
+            .position(|v| v == uri)
+            .unwrap_or(priority.len())
+    });
+    let mut titles = union(&items, |v| v.titles.clone(), |v| normalize(&v.value));
+    for title in titles.iter_mut().filter(|v| v.value.language.is_none()) {
+        let key = normalize(&title.value.value);
+        title.value.language = items
+            .iter()
+            .flat_map(|(_, v)| &v.titles)
+            .find(|v| v.language.is_some() && normalize(&v.value) == key)
+            .and_then(|v| v.language.clone());
+    }
+    MergedMetadata {
+        titles,
+        cover: pick(&items, |v| v.cover.clone()),
+        banner: pick(&items, |v| v.banner.clone()),
+        description: pick(&items, |v| v.description.clone()),
+        people: union(
+            &items,
+            |v| v.people.clone(),
+            |v| format!("{:?}{}", v.role, normalize(&v.name)),
+        ),
+        tags: union(&items, |v| v.tags.clone(), |v| normalize(v)),
+        genres: union(&items, |v| v.genres.clone(), |v| normalize(v)),
+        status: pick(&items, |v| v.status),
+        kind: pick(&items, |v| v.kind.clone()),
+        start_date: pick(&items, |v| v.start_date.clone()),
+        end_date: pick(&items, |v| v.end_date.clone()),
+        year: pick(&items, |v| v.year),
+        publishers: union(&items, |v| v.publishers.clone(), |v| normalize(&v.name)),
+        relations: union(
+            &items,
+            |v| v.relations.clone(),
+            |v| match &v.target {
+                Some((uri, id)) => format!("{uri}:{id}"),
+                None => format!("{}{}", v.kind, normalize(v.title.as_deref().unwrap_or(""))),
+            },
+        ),
+        rating: pick(&items, |v| v.rating),
+        rank: pick(&items, |v| v.rank),
+        popularity: pick(&items, |v| v.popularity),
+        latest_chapter: pick(&items, |v| v.latest_chapter),
+        external_ids: keyed(&items, |v| v.external_ids.clone()),
+        extra: keyed(&items, |v| v.extra.clone()),
+        failed: HashMap::new(),
+    }
+}
+
+fn normalize(s: &str) -> String {
+    s.to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .filter(|v| !v.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn pick<T: Clone + PartialEq>(
+    items: &[(String, MangaMetadata)],
+    get: impl Fn(&MangaMetadata) -> Option<T>,
+) -> Option<Sourced<T>> {
+    let value = items.iter().find_map(|(_, v)| get(v))?;
+    let sources = items
+        .iter()
+        .filter(|(_, v)| get(v).as_ref() == Some(&value))
+        .map(|(uri, _)| uri.clone())
+        .collect();
+    Some(Sourced { value, sources })
+}
+
+fn union<T>(
+    items: &[(String, MangaMetadata)],
+    get: impl Fn(&MangaMetadata) -> Vec<T>,
+    key: impl Fn(&T) -> String,
+) -> Vec<Sourced<T>> {
+    let mut res: Vec<Sourced<T>> = vec![];
+    let mut index = HashMap::new();
+    for (uri, data) in items {
+        for value in get(data) {
+            let key = key(&value);
+            match index.get(&key) {
+                Some(i) => {
+                    let existing: &mut Sourced<T> = &mut res[*i];
+                    if !existing.sources.contains(uri) {
+                        existing.sources.push(uri.clone());
+                    }
+                }
+                None => {
+                    index.insert(key, res.len());
+                    res.push(Sourced {
+                        value,
+                        sources: vec![uri.clone()],
+                    });
+                }
+            }
+        }
+    }
+    res
+}
+
+/// The first source of a key wins, later ones are only listed if their value is the same
+fn keyed<T: PartialEq>(
+    items: &[(String, MangaMetadata)],
+    get: impl Fn(&MangaMetadata) -> HashMap<String, T>,
+) -> HashMap<String, Sourced<T>> {
+    let mut res: HashMap<String, Sourced<T>> = HashMap::new();
+    for (uri, data) in items {
+        for (key, value) in get(data) {
+            match res.get_mut(&key) {
+                Some(existing) => {
+                    if existing.value == value {
+                        existing.sources.push(uri.clone());
+                    }
+                }
+                None => {
+                    res.insert(
+                        key,
+                        Sourced {
+                            value,
+                            sources: vec![uri.clone()],
+                        },
+                    );
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::metadata::model::Role;
+
+    fn ids(values: &[(&str, &str)]) -> MangaMetadata {
+        MangaMetadata {
+            external_ids: values
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn sources(sourced: &Sourced<impl Sized>) -> Vec<&str> {
+        sourced.sources.iter().map(|v| v.as_str()).collect()
+    }
+
+    #[test]
+    fn keyed_sources_agree() {
+        let items = vec![
+            ("anilist".to_string(), ids(&[("mal", "1"), ("kitsu", "5")])),
+            ("kitsu".to_string(), ids(&[("mal", "2"), ("kitsu", "5")])),
+            ("mangadex".to_string(), ids(&[("mal", "1")])),
+        ];
+        let merged = merge(items, &[]);
+        let mal = &merged.external_ids["mal"];
+        assert_eq!(mal.value, "1");
+        assert_eq!(mal.sources, ["anilist", "mangadex"]);
+        assert_eq!(merged.external_ids["kitsu"].sources, ["anilist", "kitsu"]);
+    }
+
+    #[test]
+    fn scalars_follow_the_priority() {
+        let anilist = MangaMetadata {
+            description: Some("long".to_string()),
+            year: Some(2012),
+            status: Some(Status::Releasing),
+            ..Default::default()
+        };
+        let kitsu = MangaMetadata {
+            description: Some("short".to_string()),
+            year: Some(2012),
+            cover: Some("https://kitsu/cover.jpg".to_string()),
+            ..Default::default()
+        };
+        let mangadex = MangaMetadata {
+            description: Some("long".to_string()),
+            status: Some(Status::Finished),
+            ..Default::default()
+        };
+        let items = || {
+            vec![
+                ("anilist".to_string(), anilist.clone()),
+                ("kitsu".to_string(), kitsu.clone()),
+                ("mangadex".to_string(), mangadex.clone()),
+            ]
+        };
+        let merged = merge(items(), &[]);
+        let description = merged.description.unwrap();
+        assert_eq!(description.value, "long");
+        assert_eq!(sources(&description), ["anilist", "mangadex"]);
+        assert_eq!(merged.status.unwrap().value, Status::Releasing);
+        // only one source has it, the others don't hide it
+        assert_eq!(sources(&merged.cover.unwrap()), ["kitsu"]);
+        assert!(merged.banner.is_none());
+
+        // listed uris come first, the rest keep their order
+        let merged = merge(items(), &["mangadex".to_string(), "kitsu".to_string()]);
+        let description = merged.description.unwrap();
+        assert_eq!(description.value, "long");
+        assert_eq!(sources(&description), ["mangadex", "anilist"]);
+        assert_eq!(merged.status.unwrap().value, Status::Finished);
+        let year = merged.year.unwrap();
+        assert_eq!(
+            (year.value, sources(&year)),
+            (2012, vec!["kitsu", "anilist"])
+        );
+        let merged = merge(items(), &["kitsu".to_string()]);
+        assert_eq!(merged.description.unwrap().value, "short");
+    }
+
+    #[test]
+    fn lists_are_deduplicated() {
+        let anilist = MangaMetadata {
+            titles: vec![Title::new("Shingeki no Kyojin", None)],
+            tags: vec!["Time Travel".to_string(), "Gore".to_string()],
+            people: vec![Person {
+                name: "Isayama Hajime".to_string(),
+                role: Role::Author,
+                id: None,
+            }],
+            ..Default::default()
+        };
+        let mut mangadex = MangaMetadata {
+            titles: vec![
+                Title::new("shingeki  no-kyojin", Some("ja-ro")),
+                Title::new("Attack on Titan", Some("en")),
+            ],
+            tags: vec!["time_travel".to_string(), "gore".to_string()],
+            ..Default::default()
+        };
+        mangadex.add_person("isayama hajime", "Story & Art", None);
+        let merged = merge(
+            vec![
+                ("anilist".to_string(), anilist),
+                ("mangadex".to_string(), mangadex),
+            ],
+            &[],
+        );
+        let titles = merged
+            .titles
+            .iter()
+            .map(|v| {
+                (
+                    v.value.value.as_str(),
+                    v.value.language.as_deref(),
+                    sources(v),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            [
+                (
+                    "Shingeki no Kyojin",
+                    Some("ja-ro"),
+                    vec!["anilist", "mangadex"]
+                ),
+                ("Attack on Titan", Some("en"), vec!["mangadex"]),
+            ]
+        );
+        let tags = merged
+            .tags
+            .iter()
+            .map(|v| (v.value.as_str(), sources(v)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            [
+                ("Time Travel", vec!["anilist", "mangadex"]),
+                ("Gore", vec!["anilist", "mangadex"]),
+            ]
+        );
+        // the same name with another role is another entry
+        let people = merged
+            .people
+            .iter()
+            .map(|v| (v.value.role.clone(), sources(v)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            people,
+            [
+                (Role::Author, vec!["anilist", "mangadex"]),
+                (Role::Artist, vec!["mangadex"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_source_is_listed_once() {
+        let tags = MangaMetadata {
+            tags: vec!["Action".to_string(), "action".to_string()],
+            ..Default::default()
+        };
+        let merged = merge(vec![("kitsu".to_string(), tags)], &[]);
+        assert_eq!(merged.tags.len(), 1);
+        assert_eq!(sources(&merged.tags[0]), ["kitsu"]);
+    }
+}
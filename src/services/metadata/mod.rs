@@ -2,18 +2,28 @@ use crate::downloader::download;
 use crate::error::ScrapeError;
 use crate::extractor::parser::clean_text;
 use crate::pages::asuratoon::get_first_url;
-use crate::pages::{anilist, kitsu, mangaupdates};
+use crate::pages::tags::normalize_tags;
+use crate::pages::{anilist, kitsu, mangadex, mangaupdates, myanimelist};
 use crate::services::icon::{get_uri, ExternalSite};
+use crate::services::metadata::merge::{merge, MergedMetadata};
+use crate::services::metadata::model::MangaMetadata;
 use crate::services::{config_to_request_builder, Service};
 use api_structure::error::{ApiErr, ApiErrorType};
+use futures::future::join_all;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
 
+pub mod merge;
+pub mod model;
+
 #[derive(Default)]
 pub struct MetaDataService {
     client: Client,
     services: HashMap<String, Service>,
+    priority: Vec<String>,
+    mal_client_id: Option<String>,
 }
 
 impl MetaDataService {
@@ -21,25 +31,93 @@ impl MetaDataService {
         Self {
             client: Default::default(),
             services,
+            priority: vec![],
+            mal_client_id: None,
         }
     }
 
+    pub(crate) fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// enables the myanimelist v2 api instead of scraping the html page
+    pub fn with_mal_client_id(mut self, client_id: Option<String>) -> Self {
+        self.mal_client_id = client_id;
+        self
+    }
+
+    /// uris in the order [`MetaDataService::merge_metadata`] prefers their values
+    pub fn with_priority(mut self, priority: Vec<String>) -> Self {
+        self.priority = priority;
+        self
+    }
+
     pub async fn get_metadata(
         &self,
         url: &str,
         data: Arc<Vec<ExternalSite>>,
-    ) -> Result<HashMap<String, ItemOrArray>, ScrapeError> {
+    ) -> Result<MangaMetadata, ScrapeError> {
         let uri = get_uri(&data, url)?;
         let url = self.process_url(&uri, url.to_string()).await;
-        if let Some(v) = self.services.get(&uri) {
+        let mut metadata = if let Some(v) = self.services.get(&uri) {
             let req = config_to_request_builder(&self.client, &v.config, url.as_str());
             let html = download(req).await?;
             let fields = v.process(html.as_str());
-            post_process(fields)
+            post_process(fields).map(MangaMetadata::from)
         } else {
-            manual(&self.client, &uri, &url).await
+            manual(&self.client, &uri, &url, self.mal_client_id.as_deref()).await
+        }?;
+        metadata.tags = normalize_tags(metadata.tags);
+        metadata.genres = normalize_tags(metadata.genres);
+        Ok(metadata)
+    }
+    /// Fetches all urls in parallel and merges the results.
+    /// Fails only when no url could be fetched.
+    pub async fn merge_metadata(
+        &self,
+        urls: &[String],
+        data: Arc<Vec<ExternalSite>>,
+    ) -> Result<MergedMetadata, ScrapeError> {
+        let results = join_all(urls.iter().map(|url| {
+            let data = data.clone();
+            async move {
+                let uri = get_uri(&data, url)?;
+                let metadata = self.get_metadata(url, data).await?;
+                Ok::<_, ScrapeError>((uri, metadata))
+            }
+        }))
+        .await;
+        self.merge_results(urls, results)
+    }
+
+    /// `results` are the (uri, metadata) of each url in `urls`
+    pub(crate) fn merge_results(
+        &self,
+        urls: &[String],
+        results: Vec<Result<(String, MangaMetadata), ScrapeError>>,
+    ) -> Result<MergedMetadata, ScrapeError> {
+        let mut items = vec![];
+        let mut failed = HashMap::new();
+        for (url, result) in urls.iter().zip(results) {
+            match result {
+                Ok(v) => items.push(v),
+                Err(e) => {
+                    failed.insert(url.clone(), e);
+                }
+            }
+        }
+        if items.is_empty() {
+            if let Some(e) = failed.into_values().next() {
+                return Err(e);
+            }
+            return Err(ScrapeError::input_error("no urls to merge"));
         }
+        let mut merged = merge(items, &self.priority);
+        merged.failed = failed;
+        Ok(merged)
     }
+
     async fn process_url(&self, uri: &str, url: String) -> String {
         if uri == "asura" {
             let html = download(self.client.get(url)).await.unwrap();
@@ -50,7 +128,8 @@ impl MetaDataService {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum ItemOrArray {
     Item(String),
     Array(Vec<String>),
@@ -108,11 +187,14 @@ async fn manual(
     client: &Client,
     uri: &str,
     url: &str,
-) -> Result<HashMap<String, ItemOrArray>, ScrapeError> {
+    mal_client_id: Option<&str>,
+) -> Result<MangaMetadata, ScrapeError> {
     match uri {
-        "manga-updates" => todo!(),
+        "manga-updates" => mangaupdates::data::get_data(client, url).await,
         "kitsu" => kitsu::get_data(client, url).await,
         "anilist" => anilist::get_data(client, url).await,
+        "myanimelist" => myanimelist::get_data(client, url, mal_client_id).await,
+        "mangadex" => mangadex::get_data(client, url).await,
         _ => Err(ApiErr {
             message: Some("uri not registered".to_string()),
             cause: None,
@@ -0,0 +1,234 @@
+use crate::extractor::parser::clean_text;
+use crate::services::metadata::ItemOrArray;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Metadata every built-in source fills.
+/// Values a source has but that don't fit a field end up in `extra`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MangaMetadata {
+    pub titles: Vec<Title>,
+    pub cover: Option<String>,
+    pub banner: Option<String>,
+    pub description: Option<String>,
+    pub people: Vec<Person>,
+    pub tags: Vec<String>,
+    pub genres: Vec<String>,
+    pub status: Option<Status>,
+    /// manga, manhwa, novel, ...
+    pub kind: Option<String>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub year: Option<i32>,
+    pub publishers: Vec<Publisher>,
+    pub relations: Vec<Relation>,
+    /// 0-10
+    pub rating: Option<f64>,
+    pub rank: Option<u64>,
+    /// rank by popularity
+    pub popularity: Option<u64>,
+    pub latest_chapter: Option<f64>,
+    /// uri => id
+    pub external_ids: HashMap<String, String>,
+    pub extra: HashMap<String, ItemOrArray>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Title {
+    pub value: String,
+    /// bcp47 like code. romanized titles use the `-ro` suffix(`ja-ro`)
+    pub language: Option<String>,
+}
+
+impl Title {
+    pub fn new(value: impl ToString, language: Option<&str>) -> Self {
+        Self {
+            value: value.to_string(),
+            language: language.map(|v| v.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Person {
+    pub name: String,
+    pub role: Role,
+    pub id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Role {
+    Author,
+    Artist,
+    Other(String),
+}
+
+impl Role {
+    /// "Story & Art" results in author and artist, whole words only,
+    /// so "Cover Artwork" or "Start" aren't artists
+    pub fn parse(s: &str) -> Vec<Self> {
+        let lower = s.to_lowercase();
+        let words = lower
+            .split(|c: char| !c.is_alphanumeric())
+            .collect::<Vec<_>>();
+        let has = |names: &[&str]| words.iter().any(|v| names.contains(v));
+        let mut roles = vec![];
+        if has(&["author", "story", "original"]) {
+            roles.push(Role::Author);
+        }
+        if has(&["artist", "art"]) {
+            roles.push(Role::Artist);
+        }
+        if roles.is_empty() {
+            roles.push(Role::Other(s.to_string()));
+        }
+        roles
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    Releasing,
+    Finished,
+    Hiatus,
+    Cancelled,
+    Upcoming,
+}
+
+impl Status {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.to_lowercase();
+        let s = s.trim();
+        if s.contains("hiatus") {
+            Some(Self::Hiatus)
+        } else if s.contains("cancel") || s.contains("discontinued") || s.contains("dropped") {
+            Some(Self::Cancelled)
+        } else if s.contains("not_yet")
+            || s.contains("upcoming")
+            || s.contains("unreleased")
+            || s.contains("not yet")
+            || s == "tba"
+        {
+            Some(Self::Upcoming)
+        } else if s.contains("releasing")
+            || s.contains("ongoing")
+            || s.contains("publishing")
+            || s == "current"
+        {
+            Some(Self::Releasing)
+        } else if s.contains("finished") || s.contains("complete") {
+            Some(Self::Finished)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Publisher {
+    pub name: String,
+    /// original, english, ...
+    pub kind: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Relation {
+    /// sequel, adaptation, ...
+    pub kind: String,
+    pub title: Option<String>,
+    pub media_type: Option<String>,
+    /// uri => id of the related entry
+    pub target: Option<(String, String)>,
+}
+
+impl MangaMetadata {
+    pub fn add_person(&mut self, name: impl ToString, role: &str, id: Option<String>) {
+        for role in Role::parse(role) {
+            self.people.push(Person {
+                name: name.to_string(),
+                role,
+                id: id.clone(),
+            });
+        }
+    }
+
+    pub fn insert_extra(&mut self, key: &str, value: impl ToString) {
+        self.extra
+            .insert(key.to_string(), ItemOrArray::Item(value.to_string()));
+    }
+}
+
+/// Used for the generic scrapers which only return key/value pairs
+impl From<HashMap<String, ItemOrArray>> for MangaMetadata {
+    fn from(values: HashMap<String, ItemOrArray>) -> Self {
+        let mut res = MangaMetadata::default();
+        for (key, value) in values {
+            let items = match &value {
+                ItemOrArray::Item(v) => vec![v.clone()],
+                ItemOrArray::Array(v) => v.clone(),
+            };
+            let first = items.first().map(|v| clean_text(v.to_string()));
+            match key.to_lowercase().as_str() {
+                "title" | "titles" | "synonyms" | "alternative" | "alternative_titles" => res
+                    .titles
+                    .append(&mut items.into_iter().map(|v| Title::new(v, None)).collect()),
+                "english" => res
+                    .titles
+                    .push(Title::new(first.unwrap_or_default(), Some("en"))),
+                "japanese" => res
+                    .titles
+                    .push(Title::new(first.unwrap_or_default(), Some("ja"))),
+                "cover" | "img" | "image" | "poster" => res.cover = first,
+                "banner" => res.banner = first,
+                "description" | "synopsis" | "summary" => res.description = first,
+                "tags" | "themes" | "categories" => res.tags.extend(items),
+                "genres" | "genre" | "demographic" => res.genres.extend(items),
+                "status" => res.status = first.as_deref().and_then(Status::parse),
+                "type" | "format" => res.kind = first,
+                "author" | "authors" => {
+                    for v in items {
+                        res.add_person(v, "author", None)
+                    }
+                }
+                "artist" | "artists" => {
+                    for v in items {
+                        res.add_person(v, "artist", None)
+                    }
+                }
+                "publisher" | "publishers" | "serialization" => res.publishers.append(
+                    &mut items
+                        .into_iter()
+                        .map(|name| Publisher { name, kind: None })
+                        .collect(),
+                ),
+                "year" => res.year = first.and_then(|v| v.parse().ok()),
+                "start_date" | "published" => res.start_date = first,
+                "end_date" => res.end_date = first,
+                _ => {
+                    res.extra.insert(key, value);
+                }
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_roles() {
+        assert_eq!(Role::parse("Story & Art"), [Role::Author, Role::Artist]);
+        assert_eq!(Role::parse("Original Creator"), [Role::Author]);
+        assert_eq!(Role::parse("Artist"), [Role::Artist]);
+        assert_eq!(
+            Role::parse("Cover Artwork"),
+            [Role::Other("Cover Artwork".to_string())]
+        );
+        assert_eq!(
+            Role::parse("Assistant (Start)"),
+            [Role::Other("Assistant (Start)".to_string())]
+        );
+    }
+}
@@ -1,8 +1,10 @@
 use crate::error::ScrapeError;
 use crate::extractor::parser::Field;
 use crate::extractor::SearchServiceDeserialized;
+use crate::services::feed::FeedDeserialized;
 use crate::services::metadata::MetaDataService;
-use crate::services::multisite::MultiSiteService;
+use crate::services::multisite::{DuplicatePolicy, MultiSiteService};
+use crate::services::pagination::Pagination;
 use crate::services::search::SearchService;
 use crate::services::singlesite::SingleSiteService;
 use reqwest::header::{HeaderName, HeaderValue};
@@ -15,15 +17,24 @@ use std::io::{read_to_string, BufRead};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+pub mod feed;
 pub mod icon;
 pub mod metadata;
 pub mod multisite;
+pub mod pagination;
+pub mod scraper;
 pub mod search;
 pub mod singlesite;
+pub mod tracker;
 
 pub struct Service {
     fields: Vec<Field>,
     config: HashMap<String, String>,
+    duplicates: DuplicatePolicy,
+    /// chapter list
+    pagination: Option<Pagination>,
+    /// images of the reader
+    page_pagination: Option<Pagination>,
 }
 
 impl Service {
@@ -51,6 +62,7 @@ pub fn init(
     let mut meta = HashMap::new();
     let mut multi = HashMap::new();
     let mut single = HashMap::new();
+    let mut feeds = HashMap::new();
     for entry in read_dir(&folder)? {
         let path = entry?.path();
         if path.is_file() {
@@ -82,12 +94,16 @@ pub fn init(
                     let str = read_to_string(file)?;
                     let data: SearchServiceDeserialized = serde_json::from_str(&str)?;
                     search.insert(v.to_string(), data.convert(&folder));
+                } else if let Some(v) = name.strip_suffix(".feed") {
+                    let data: FeedDeserialized =
+                        serde_json::from_str(&read_to_string(File::open(path.as_path())?)?)?;
+                    feeds.insert(v.to_string(), data.convert(&folder)?);
                 }
             }
         }
     }
     Ok((
-        MultiSiteService::new(multi),
+        MultiSiteService::new(multi).with_feeds(feeds),
         SingleSiteService::new(single),
         SearchService::new(search),
         MetaDataService::new(meta),
@@ -111,7 +127,16 @@ fn get_services(folder: &Path, path: &PathBuf) -> Result<(Service, Option<Kind>)
         } else {
             HashMap::new()
         };
-        Ok((Service { fields: v, config }, header.kind))
+        Ok((
+            Service {
+                fields: v,
+                config,
+                duplicates: header.duplicates,
+                pagination: header.pagination,
+                page_pagination: header.page_pagination,
+            },
+            header.kind,
+        ))
     } else {
         Err(ScrapeError::input_error(format!(
             "header missing in file: {}",
@@ -124,6 +149,10 @@ fn get_services(folder: &Path, path: &PathBuf) -> Result<(Service, Option<Kind>)
 struct Header {
     kind: Option<Kind>,
     request_config: Option<String>,
+    #[serde(default)]
+    duplicates: DuplicatePolicy,
+    pagination: Option<Pagination>,
+    page_pagination: Option<Pagination>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
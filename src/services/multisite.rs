@@ -1,20 +1,34 @@
-use crate::downloader::download;
+use crate::extractor::date::{self, parse_date};
+use crate::extractor::episode::Episode;
+use crate::extractor::parser::clean_text;
 use crate::pages::hidden;
+use crate::pages::mangadex::{self, MangaDexOptions};
+use crate::pages::mangaupdates;
+use crate::services::feed::FeedSource;
 use crate::services::icon::get_uri;
-use crate::services::{config_to_request_builder, Service};
+use crate::services::pagination::Paginate;
+use crate::services::{pagination, Service};
 use crate::{ExternalSite, ScrapeError};
 use api_structure::error::{ApiErr, ApiErrorType};
 use api_structure::scrape::ScrapeAccount;
-use regex::Regex;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-#[derive(Default)]
 pub struct MultiSiteService {
     client: Client,
     services: HashMap<String, Service>,
+    feeds: HashMap<String, FeedSource>,
+    mangadex: MangaDexOptions,
+    /// policies of the built-in sources, scrapers and feeds declare theirs in the file
+    duplicates: HashMap<String, DuplicatePolicy>,
+}
+
+impl Default for MultiSiteService {
+    fn default() -> Self {
+        Self::new(HashMap::new())
+    }
 }
 
 impl MultiSiteService {
@@ -22,20 +36,49 @@ impl MultiSiteService {
         Self {
             client: Default::default(),
             services,
+            feeds: HashMap::new(),
+            mangadex: Default::default(),
+            duplicates: builtin_duplicates(),
         }
     }
+
+    pub(crate) fn with_feeds(mut self, feeds: HashMap<String, FeedSource>) -> Self {
+        self.feeds = feeds;
+        self
+    }
+
+    pub(crate) fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// chapter languages and image quality of mangadex
+    pub fn with_mangadex(mut self, options: MangaDexOptions) -> Self {
+        self.mangadex = options;
+        self
+    }
+
+    /// replaces the [`DuplicatePolicy`] of a built-in source like `mangadex`
+    pub fn with_duplicates(mut self, uri: impl ToString, policy: DuplicatePolicy) -> Self {
+        self.duplicates.insert(uri.to_string(), policy);
+        self
+    }
     pub async fn get_chapters(
         &self,
         url: &str,
         data: Arc<Vec<ExternalSite>>,
-    ) -> Result<(Vec<Info>, Vec<Info>), ScrapeError> {
+    ) -> Result<ChapterList, ScrapeError> {
         let uri = get_uri(&data, url)?;
         let url = modify_url(&self.client, &uri, url).await;
-        let (mut now, mut later) = {
-            if let Some(v) = self.services.get(&uri) {
-                let req = config_to_request_builder(&self.client, &v.config, &url);
-                let html = download(req).await?;
-                let fields = v.process(html.as_str());
+        let (now, later) = {
+            if let Some(v) = self.feeds.get(&uri) {
+                v.get_chapters(&self.client, &uri, &url)
+                    .await
+                    .map(split_later)
+            } else if let Some(v) = self.services.get(&uri) {
+                let fields =
+                    pagination::fetch_all(&self.client, v, &url, "urls", Paginate::Chapters)
+                        .await?;
                 let items = post_process(uri.as_str(), fields)
                     .map(|v| {
                         v.into_iter()
@@ -56,47 +99,43 @@ impl MultiSiteService {
                             })
                             .collect::<Vec<_>>()
                     })
-                    .map(|v| (v, vec![]))?;
+                    .map(split_later)?;
                 Ok(items)
             } else {
-                manual(&self.client, uri.as_str(), &url).await
+                manual(&self.client, uri.as_str(), &url, &self.mangadex).await
             }
         }?;
-        let mut existing = HashSet::new();
-        let mut last = 0.0;
-        let fix = false;
-        for now in &mut now {
-            let mut increase = false;
-            if now.episode == 0.0 {
-                if fix {
-                    increase = true;
-                    now.episode = last;
-                } else {
-                    return Err(ScrapeError::input_error("failed to parse episode"));
-                }
-            }
-            while existing.contains(&format!("{:.2}", now.episode)) {
-                if increase {
-                    now.episode += 0.01;
-                } else {
-                    return Err(ScrapeError::input_error("episode does already exist"));
-                }
-            }
-            now.episode = cut_float(now.episode);
-            last = now.episode;
-
-            existing.insert(format!("{:.2}", now.episode));
-        }
-        for later in &mut later {
-            if later.episode == 0.0 {
-                return Err(ScrapeError::input_error("failed to parse episode"));
-            }
-            if existing.contains(&format!("{:.2}", later.episode)) {
-                return Err(ScrapeError::input_error("episode does already exist"));
+        let policy = match (self.feeds.get(&uri), self.services.get(&uri)) {
+            (Some(v), _) => v.duplicates.clone(),
+            (None, Some(v)) => v.duplicates.clone(),
+            (None, None) => self.duplicates.get(&uri).cloned().unwrap_or_default(),
+        };
+        let mut warnings = vec![];
+        let now = resolve_duplicates(now, &policy, &mut warnings)?;
+        let existing = now.iter().map(|v| policy.key(v)).collect::<HashSet<_>>();
+        let mut checked_later = vec![];
+        for later in later {
+            let problem = if later.unparsed {
+                "failed to parse episode"
+            } else if existing.contains(&policy.key(&later)) {
+                "episode does already exist"
+            } else {
+                checked_later.push(later);
+                continue;
+            };
+            if let DuplicatePolicy::Reject = policy {
+                return Err(ScrapeError::input_error(problem));
             }
-            existing.insert(format!("{:.2}", later.episode));
+            warnings.push(format!(
+                "{}: {problem}, skipped scheduled chapter",
+                later.url
+            ));
         }
-        Ok((now, later))
+        Ok(ChapterList {
+            now,
+            later: checked_later,
+            warnings,
+        })
     }
 
     pub async fn get_pages(
@@ -105,30 +144,223 @@ impl MultiSiteService {
         acc: Option<ScrapeAccount>,
     ) -> Result<Vec<String>, ScrapeError> {
         if let Some(v) = self.services.get(&info.site) {
-            let req = config_to_request_builder(&self.client, &v.config, &info.url);
-            let html = download(req).await?;
-            let fields = v.process(html.as_str());
+            let fields =
+                pagination::fetch_all(&self.client, v, &info.url, "imgs", Paginate::Pages).await?;
             post_process_pages(&info.site.as_str(), fields)
         } else {
-            manual_pages(&self.client, info, acc).await
+            manual_pages(&self.client, info, acc, &self.mangadex).await
+        }
+    }
+}
+
+/// What happens when a chapter has no episode number or collides with another chapter
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// fail the whole list
+    #[default]
+    Reject,
+    KeepFirst,
+    KeepLast,
+    /// move the chapter by 0.01 until it's unique,
+    /// chapters without a number follow the one before them.
+    /// The other policies drop chapters without a number
+    Bump,
+    /// keep the first chapter and add the titles of the others
+    MergeTitles,
+    /// chapters of the same episode and language from different groups are duplicates,
+    /// the group listed first wins
+    PreferGroup(Vec<String>),
+}
+
+impl DuplicatePolicy {
+    fn key(&self, info: &Info) -> String {
+        match self {
+            DuplicatePolicy::PreferGroup(_) => info.key(false),
+            _ => info.key(true),
+        }
+    }
+
+    fn group_rank(&self, info: &Info) -> usize {
+        match self {
+            DuplicatePolicy::PreferGroup(groups) => info
+                .groups
+                .iter()
+                .filter_map(|g| groups.iter().position(|v| v.eq_ignore_ascii_case(g)))
+                .min()
+                .unwrap_or(groups.len()),
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChapterList {
+    pub now: Vec<Info>,
+    /// scheduled or locked chapters
+    pub later: Vec<Info>,
+    /// adjustments made by the [`DuplicatePolicy`]
+    pub warnings: Vec<String>,
+}
+
+impl ChapterList {
+    /// Earliest known release of the scheduled chapters
+    pub fn next_release(&self) -> Option<i64> {
+        self.later.iter().filter_map(|v| v.release_at).min()
+    }
+}
+
+/// Locked and not yet released chapters are moved to `later`
+fn split_later(items: Vec<Info>) -> (Vec<Info>, Vec<Info>) {
+    let now = date::now();
+    items
+        .into_iter()
+        .partition(|v| !v.locked && v.release_at.map(|v| v <= now).unwrap_or(true))
+}
+
+/// Sites that list a chapter once per upload, a re-upload shouldn't fail the list.
+/// Oneshots on mangadex have no number and are placed after the chapter before them
+fn builtin_duplicates() -> HashMap<String, DuplicatePolicy> {
+    [
+        ("mangadex".to_string(), DuplicatePolicy::Bump),
+        ("manga-updates".to_string(), DuplicatePolicy::KeepFirst),
+    ]
+    .into_iter()
+    .collect()
+}
+
+fn resolve_duplicates(
+    items: Vec<Info>,
+    policy: &DuplicatePolicy,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<Info>, ScrapeError> {
+    let mut res: Vec<Info> = vec![];
+    let mut existing: HashMap<String, usize> = HashMap::new();
+    let mut last = 0.0;
+    for mut info in items {
+        if info.unparsed {
+            match policy {
+                DuplicatePolicy::Reject => {
+                    return Err(ScrapeError::input_error("failed to parse episode"))
+                }
+                DuplicatePolicy::Bump => {
+                    info.episode = last;
+                    while existing.contains_key(&policy.key(&info)) {
+                        info.episode = cut_float(info.episode + 0.01);
+                    }
+                    warnings.push(format!(
+                        "{}: failed to parse episode, using {}",
+                        info.url, info.episode
+                    ));
+                }
+                _ => {
+                    warnings.push(format!("{}: failed to parse episode, dropped", info.url));
+                    continue;
+                }
+            }
+        }
+        info.episode = cut_float(info.episode);
+        if let Some(i) = existing.get(&policy.key(&info)).copied() {
+            let episode = info.episode;
+            match policy {
+                DuplicatePolicy::Reject => {
+                    return Err(ScrapeError::input_error("episode does already exist"))
+                }
+                DuplicatePolicy::KeepFirst => {
+                    warnings.push(format!("{}: episode {episode} dropped", info.url));
+                    continue;
+                }
+                DuplicatePolicy::KeepLast => {
+                    warnings.push(format!("{}: episode {episode} dropped", res[i].url));
+                    res[i] = info;
+                    continue;
+                }
+                DuplicatePolicy::MergeTitles => {
+                    for title in info.titles {
+                        if !res[i].titles.contains(&title) {
+                            res[i].titles.push(title);
+                        }
+                    }
+                    warnings.push(format!(
+                        "{}: episode {episode} merged into {}",
+                        info.url, res[i].url
+                    ));
+                    continue;
+                }
+                DuplicatePolicy::PreferGroup(_) => {
+                    let dropped = match policy.group_rank(&info) < policy.group_rank(&res[i]) {
+                        true => std::mem::replace(&mut res[i], info),
+                        false => info,
+                    };
+                    warnings.push(format!("{}: episode {episode} dropped", dropped.url));
+                    continue;
+                }
+                DuplicatePolicy::Bump => {
+                    while existing.contains_key(&policy.key(&info)) {
+                        info.episode = cut_float(info.episode + 0.01);
+                    }
+                    warnings.push(format!(
+                        "{}: episode {episode} does already exist, using {}",
+                        info.url, info.episode
+                    ));
+                }
+            }
         }
+        last = info.episode;
+        existing.insert(policy.key(&info), res.len());
+        res.push(info);
     }
+    Ok(res)
 }
 
 fn cut_float(f: f64) -> f64 {
     format!("{:.2}", f).parse().unwrap()
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Info {
     pub site: String,
     pub url: String,
     pub titles: Vec<String>,
     pub episode: f64,
     pub account: Option<i64>,
+    #[serde(default)]
+    pub language: Option<String>,
+    /// scanlation groups
+    #[serde(default)]
+    pub groups: Vec<String>,
+    #[serde(default)]
+    pub volume: Option<f64>,
+    /// date as the site shows it
+    #[serde(default)]
+    pub published_at: Option<String>,
+    #[serde(default)]
+    pub page_count: Option<u32>,
+    /// needs coins, an account or waiting
+    #[serde(default)]
+    pub locked: bool,
+    /// unix seconds
+    #[serde(default)]
+    pub release_at: Option<i64>,
+    /// no episode number was found, `episode` is only a guess
+    #[serde(default)]
+    pub unparsed: bool,
 }
 
 impl Info {
+    /// The same episode can exist once per language and group
+    fn key(&self, with_group: bool) -> String {
+        let groups = match with_group {
+            true => self.groups.join(","),
+            false => String::new(),
+        };
+        format!(
+            "{:.2}|{}|{groups}",
+            self.episode,
+            self.language.as_deref().unwrap_or_default(),
+        )
+    }
+
     pub fn add_title(mut self, title: &Option<String>) -> Self {
         if let Some(v) = title {
             self.titles.push(v.to_string())
@@ -137,28 +369,6 @@ impl Info {
     }
 }
 
-pub fn parse_episode(s: &str) -> Result<f64, ScrapeError> {
-    let re = Regex::new(r"chapter\s+(\d+(\.\d+)?)").unwrap();
-    let re2 = Regex::new(r"ch\.\s+(\d+(\.\d+)?)").unwrap();
-    if let Some(captured) = re.captures(&s.to_lowercase()) {
-        let number_str = &captured[1];
-        Ok(number_str.parse()?)
-    } else if let Some(captured) = re2.captures(&s.to_lowercase()) {
-        let number_str = &captured[1];
-        Ok(number_str.parse()?)
-    } else if let Some(captured) = Regex::new(r"第(\d+(\.\d+)?)").unwrap().captures(&s) {
-        let number_str = &captured[1];
-        Ok(number_str.parse()?)
-    } else {
-        Err(ApiErr {
-            message: Some("couldnt find chapter number".to_string()),
-            cause: None,
-            err_type: ApiErrorType::InternalError,
-        }
-        .into())
-    }
-}
-
 fn post_process(uri: &str, fields: HashMap<String, String>) -> Result<Vec<Info>, ScrapeError> {
     let err = |len1, len2| {
         if len1 != len2 || len2 == 0 {
@@ -179,16 +389,17 @@ fn post_process(uri: &str, fields: HashMap<String, String>) -> Result<Vec<Info>,
             err(labels.len(), urls.len())?;
             for (i, url) in urls.into_iter().enumerate() {
                 let title = labels.get(i).unwrap().to_string();
-                let episode = parse_episode(title.as_str()).unwrap_or(0.0);
+                let parsed = Episode::parse(title.as_str());
                 res.push(Info {
                     site: uri.to_string(),
                     url,
                     titles: vec![title],
-                    episode,
-                    account: None,
+                    episode: parsed.chapter.unwrap_or(0.0),
+                    unparsed: parsed.chapter.is_none(),
+                    volume: parsed.volume,
+                    ..Default::default()
                 })
             }
-            return Ok(res);
         } else if let Some(episodes) = fields.get("episodes") {
             let episodes: Vec<String> = serde_json::from_str(episodes)?;
             err(episodes.len(), urls.len())?;
@@ -200,29 +411,116 @@ fn post_process(uri: &str, fields: HashMap<String, String>) -> Result<Vec<Info>,
                     url,
                     titles: vec![title],
                     episode,
-                    account: None,
+                    ..Default::default()
                 })
             }
-            return Ok(res);
+        } else {
+            return hidden::multi::post_process_info(uri, fields);
         }
+        add_details(&mut res, &fields)?;
+        return Ok(res);
     }
     hidden::multi::post_process_info(uri, fields)
 }
 
+/// Optional per chapter fields, they have to line up with `urls`
+fn add_details(items: &mut [Info], fields: &HashMap<String, String>) -> Result<(), ScrapeError> {
+    let len = items.len();
+    let get = |key: &str| -> Result<Option<Vec<String>>, ScrapeError> {
+        match fields.get(key) {
+            Some(v) => {
+                let values: Vec<String> = serde_json::from_str(v)?;
+                if values.len() != len {
+                    return Err(ScrapeError::input_error(format!(
+                        "{key} doesnt match the number of urls"
+                    )));
+                }
+                Ok(Some(values.into_iter().map(clean_text).collect()))
+            }
+            None => Ok(None),
+        }
+    };
+    let non_empty = |v: String| match v.is_empty() {
+        true => None,
+        false => Some(v),
+    };
+    if let Some(values) = get("languages")? {
+        for (item, v) in items.iter_mut().zip(values) {
+            item.language = non_empty(v);
+        }
+    }
+    if let Some(values) = get("groups")? {
+        for (item, v) in items.iter_mut().zip(values) {
+            item.groups = non_empty(v).into_iter().collect();
+        }
+    }
+    if let Some(values) = get("volumes")? {
+        for (item, v) in items.iter_mut().zip(values) {
+            item.volume = parse_number(&v);
+        }
+    }
+    let now = date::now();
+    if let Some(values) = get("dates")? {
+        for (item, v) in items.iter_mut().zip(values) {
+            // a date in the future is the release of a scheduled chapter
+            item.release_at = parse_date(&v, now).filter(|v| *v > now);
+            item.published_at = non_empty(v);
+        }
+    }
+    if let Some(values) = get("release_dates")? {
+        for (item, v) in items.iter_mut().zip(values) {
+            item.release_at = parse_date(&v, now);
+        }
+    }
+    if let Some(values) = get("locked")? {
+        for (item, v) in items.iter_mut().zip(values) {
+            let v = v.to_lowercase();
+            item.locked = !matches!(v.as_str(), "" | "false" | "0" | "no");
+        }
+    }
+    if let Some(values) = get("page_counts")? {
+        for (item, v) in items.iter_mut().zip(values) {
+            item.page_count = parse_number(&v).map(|v| v as u32);
+        }
+    }
+    Ok(())
+}
+
+/// first number in `s`, "Vol. 3" => 3
+fn parse_number(s: &str) -> Option<f64> {
+    let start = s.find(|c: char| c.is_ascii_digit())?;
+    let s = &s[start..];
+    let end = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    s[..end].trim_end_matches('.').parse().ok()
+}
+
 async fn manual(
     client: &Client,
     uri: &str,
     url: &str,
+    mangadex: &MangaDexOptions,
 ) -> Result<(Vec<Info>, Vec<Info>), ScrapeError> {
-    hidden::multi::manual_info(client, uri, url).await
+    match uri {
+        "mangadex" => mangadex::get_chapters(client, url, mangadex).await,
+        "manga-updates" => mangaupdates::data::get_releases(client, url)
+            .await
+            .map(split_later),
+        _ => hidden::multi::manual_info(client, uri, url).await,
+    }
 }
 
 async fn manual_pages(
     client: &Client,
     info: Info,
     acc: Option<ScrapeAccount>,
+    mangadex: &MangaDexOptions,
 ) -> Result<Vec<String>, ScrapeError> {
-    hidden::multi::manual_pages(client, info, acc).await
+    match info.site.as_str() {
+        "mangadex" => mangadex::get_pages(client, &info, mangadex).await,
+        _ => hidden::multi::manual_pages(client, info, acc).await,
+    }
 }
 
 fn post_process_pages(
@@ -264,3 +562,135 @@ pub async fn modify_url(client: &Client, uri: &str, url: &str) -> String {
         url.to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(url: &str, episode: f64, unparsed: bool) -> Info {
+        Info {
+            url: url.to_string(),
+            episode,
+            unparsed,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn chapter_zero_is_an_episode() {
+        let items = vec![info("a", 0.0, false), info("b", 1.0, false)];
+        let res = resolve_duplicates(items, &DuplicatePolicy::Reject, &mut vec![]).unwrap();
+        assert_eq!(res.len(), 2);
+    }
+
+    fn grouped(url: &str, episode: f64, group: &str) -> Info {
+        Info {
+            titles: vec![url.to_string()],
+            groups: vec![group.to_string()],
+            ..info(url, episode, false)
+        }
+    }
+
+    fn urls(items: &[Info]) -> Vec<(&str, f64)> {
+        items.iter().map(|v| (v.url.as_str(), v.episode)).collect()
+    }
+
+    #[test]
+    fn unparsed_episodes() {
+        let items = || {
+            vec![
+                info("a", 1.0, false),
+                info("b", 0.0, true),
+                info("c", 2.0, false),
+            ]
+        };
+        assert!(resolve_duplicates(items(), &DuplicatePolicy::Reject, &mut vec![]).is_err());
+        let cases = [
+            (
+                DuplicatePolicy::Bump,
+                vec![("a", 1.0), ("b", 1.01), ("c", 2.0)],
+            ),
+            (DuplicatePolicy::KeepFirst, vec![("a", 1.0), ("c", 2.0)]),
+            (DuplicatePolicy::KeepLast, vec![("a", 1.0), ("c", 2.0)]),
+            (DuplicatePolicy::MergeTitles, vec![("a", 1.0), ("c", 2.0)]),
+            (
+                DuplicatePolicy::PreferGroup(vec![]),
+                vec![("a", 1.0), ("c", 2.0)],
+            ),
+        ];
+        for (policy, expected) in cases {
+            let mut warnings = vec![];
+            let res = resolve_duplicates(items(), &policy, &mut warnings).unwrap();
+            assert_eq!(urls(&res), expected, "{policy:?}");
+            assert_eq!(warnings.len(), 1, "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn duplicate_episodes() {
+        let items = || {
+            vec![
+                grouped("a", 1.0, "x"),
+                grouped("b", 1.0, "x"),
+                grouped("c", 1.0, "y"),
+                grouped("d", 2.0, "x"),
+            ]
+        };
+        assert!(resolve_duplicates(items(), &DuplicatePolicy::Reject, &mut vec![]).is_err());
+        let prefer = |groups: &[&str]| {
+            DuplicatePolicy::PreferGroup(groups.iter().map(|v| v.to_string()).collect())
+        };
+        let cases = [
+            (
+                DuplicatePolicy::KeepFirst,
+                vec![("a", 1.0), ("c", 1.0), ("d", 2.0)],
+                1,
+            ),
+            (
+                DuplicatePolicy::KeepLast,
+                vec![("b", 1.0), ("c", 1.0), ("d", 2.0)],
+                1,
+            ),
+            (
+                DuplicatePolicy::Bump,
+                vec![("a", 1.0), ("b", 1.01), ("c", 1.0), ("d", 2.0)],
+                1,
+            ),
+            (
+                DuplicatePolicy::MergeTitles,
+                vec![("a", 1.0), ("c", 1.0), ("d", 2.0)],
+                1,
+            ),
+            (prefer(&["y", "x"]), vec![("c", 1.0), ("d", 2.0)], 2),
+            (prefer(&["X"]), vec![("a", 1.0), ("d", 2.0)], 2),
+            (prefer(&[]), vec![("a", 1.0), ("d", 2.0)], 2),
+        ];
+        for (policy, expected, dropped) in cases {
+            let mut warnings = vec![];
+            let res = resolve_duplicates(items(), &policy, &mut warnings).unwrap();
+            assert_eq!(urls(&res), expected, "{policy:?}");
+            assert_eq!(warnings.len(), dropped, "{policy:?}");
+        }
+        let res = resolve_duplicates(items(), &DuplicatePolicy::MergeTitles, &mut vec![]).unwrap();
+        assert_eq!(res[0].titles, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn group_rank() {
+        let policy = DuplicatePolicy::PreferGroup(vec!["a".to_string(), "b".to_string()]);
+        let mut info = grouped("u", 1.0, "B");
+        assert_eq!(policy.group_rank(&info), 1);
+        info.groups.push("a".to_string());
+        assert_eq!(policy.group_rank(&info), 0);
+        assert_eq!(policy.group_rank(&grouped("u", 1.0, "c")), 2);
+        assert_eq!(DuplicatePolicy::KeepFirst.group_rank(&info), 0);
+    }
+
+    #[test]
+    fn builtin_sources_have_a_policy() {
+        let service = MultiSiteService::default();
+        assert_eq!(service.duplicates["mangadex"], DuplicatePolicy::Bump);
+        let service = service.with_duplicates("mangadex", DuplicatePolicy::KeepLast);
+        assert_eq!(service.duplicates["mangadex"], DuplicatePolicy::KeepLast);
+    }
+}
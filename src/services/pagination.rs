@@ -0,0 +1,332 @@
+use crate::downloader::download;
+use crate::services::{config_to_request_builder, Service};
+use crate::ScrapeError;
+use futures::{stream, StreamExt};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Declared in the scraper header, `pagination` for the chapter list and
+/// `page_pagination` for the images of the reader, for example
+/// `"pagination": {"template": "{url}?page={page}"}`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pagination {
+    /// field containing the url of the next page
+    pub next: Option<String>,
+    /// `{url}` is the first page, `{page}` the page number
+    pub template: Option<String>,
+    /// field with the number of pages, with `template` all pages are loaded at once
+    pub count: Option<String>,
+    /// first page number used with `template`
+    #[serde(default = "default_start")]
+    pub start: u32,
+    #[serde(default = "default_max_pages")]
+    pub max_pages: u32,
+    /// only follow `next` links below the path of the first url,
+    /// so readers don't continue with the next chapter
+    #[serde(default)]
+    pub within_url: bool,
+    pub ajax: Option<Ajax>,
+}
+
+/// Request whose response contains the list, `{id}` and `{url}` are replaced
+/// in `url` and `body`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Ajax {
+    pub url: String,
+    pub method: Option<String>,
+    pub body: Option<String>,
+    pub content_type: Option<String>,
+    /// field of the first page with the series id
+    pub id: Option<String>,
+}
+
+fn default_start() -> u32 {
+    2
+}
+
+fn default_max_pages() -> u32 {
+    100
+}
+
+const CONCURRENT_PAGES: usize = 8;
+
+/// Which pagination of the service [`fetch_all`] follows
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Paginate {
+    Chapters,
+    Pages,
+}
+
+/// Downloads `url` and every following page, `key` is the field that lists the items.
+/// Pages are merged by appending the array fields.
+pub(crate) async fn fetch_all(
+    client: &Client,
+    service: &Service,
+    url: &str,
+    key: &str,
+    paginate: Paginate,
+) -> Result<HashMap<String, String>, ScrapeError> {
+    let mut fields = fetch(client, service, url).await?;
+    let pagination = match paginate {
+        Paginate::Chapters => &service.pagination,
+        Paginate::Pages => &service.page_pagination,
+    };
+    let pagination = match pagination {
+        Some(v) => v,
+        None => return Ok(fields),
+    };
+    if let Some(ajax) = &pagination.ajax {
+        let id = first_value(&fields, ajax.id.as_deref().unwrap_or("id")).unwrap_or_default();
+        let replace = |s: &str| s.replace("{id}", &id).replace("{url}", url);
+        let mut config = service.config.clone();
+        config.insert(
+            "METHOD".to_string(),
+            ajax.method.clone().unwrap_or("POST".to_string()),
+        );
+        let mut req = config_to_request_builder(client, &config, &replace(&ajax.url))
+            .header("X-Requested-With", "XMLHttpRequest");
+        if let Some(body) = &ajax.body {
+            req = req.body(replace(body)).header(
+                "Content-Type",
+                ajax.content_type
+                    .as_deref()
+                    .unwrap_or("application/x-www-form-urlencoded"),
+            );
+        }
+        let html = download(req).await?;
+        // the response replaces the values of the first page
+        fields.extend(service.process(&html));
+    }
+    let mut seen: HashSet<String> = values(&fields, key).into_iter().collect();
+    let link = |page: u32, template: &str| {
+        template
+            .replace("{url}", url.trim_end_matches('/'))
+            .replace("{page}", &page.to_string())
+    };
+    let count = pagination
+        .count
+        .as_deref()
+        .and_then(|v| page_count(&fields, v));
+    if let (Some(count), Some(template)) = (count, &pagination.template) {
+        // the first page is already loaded
+        let pages = stream::iter(
+            (pagination.start..)
+                .take(count.saturating_sub(1).min(pagination.max_pages) as usize)
+                .map(|page| link(page, template)),
+        )
+        .map(|link| async move { fetch(client, service, &link).await })
+        .buffered(CONCURRENT_PAGES)
+        .collect::<Vec<_>>()
+        .await;
+        for page in pages {
+            merge(&mut fields, page?, key, &mut seen);
+        }
+    } else if let Some(next) = &pagination.next {
+        let mut current = url.to_string();
+        let mut next_url = first_value(&fields, next);
+        for _ in 0..pagination.max_pages {
+            let link = match next_url.as_deref().and_then(|v| resolve(&current, v)) {
+                Some(v) if v == current => break,
+                Some(v) if pagination.within_url && !within(url, &v) => break,
+                Some(v) => v,
+                None => break,
+            };
+            let page = fetch(client, service, &link).await?;
+            next_url = first_value(&page, next);
+            current = link;
+            if !merge(&mut fields, page, key, &mut seen) {
+                break;
+            }
+        }
+    } else if let Some(template) = &pagination.template {
+        for page in pagination.start..pagination.start + pagination.max_pages {
+            let page = fetch(client, service, &link(page, template)).await?;
+            // sites show the last or an empty page after the end
+            if !merge(&mut fields, page, key, &mut seen) {
+                break;
+            }
+        }
+    }
+    Ok(fields)
+}
+
+async fn fetch(
+    client: &Client,
+    service: &Service,
+    url: &str,
+) -> Result<HashMap<String, String>, ScrapeError> {
+    let req = config_to_request_builder(client, &service.config, url);
+    let html = download(req).await?;
+    Ok(service.process(&html))
+}
+
+/// Adds the items of `page` whose `key` wasn't seen yet, returns false if there were none.
+/// Array fields with one value per item stay aligned with `key`, a field missing on
+/// some pages is padded with empty values. Other fields keep the value of the first page
+fn merge(
+    fields: &mut HashMap<String, String>,
+    page: HashMap<String, String>,
+    key: &str,
+    seen: &mut HashSet<String>,
+) -> bool {
+    let new = values(&page, key);
+    let fresh = (0..new.len())
+        .filter(|i| seen.insert(new[*i].clone()))
+        .collect::<Vec<_>>();
+    if fresh.is_empty() {
+        return false;
+    }
+    let len = values(fields, key).len();
+    let mut columns = item_columns(fields, len);
+    columns.insert(key.to_string(), values(fields, key));
+    let page_columns = item_columns(&page, new.len());
+    for name in page_columns.keys() {
+        columns
+            .entry(name.clone())
+            .or_insert_with(|| vec![String::new(); len]);
+    }
+    for i in fresh {
+        for (name, column) in columns.iter_mut() {
+            let value = match name == key {
+                true => Some(&new[i]),
+                false => page_columns.get(name).map(|v| &v[i]),
+            };
+            column.push(value.cloned().unwrap_or_default());
+        }
+    }
+    for (name, value) in page {
+        if !columns.contains_key(&name) {
+            fields.entry(name).or_insert(value);
+        }
+    }
+    for (name, column) in columns {
+        fields.insert(name, serde_json::to_string(&column).unwrap_or_default());
+    }
+    true
+}
+
+/// Array fields with `len` values, so one value per item
+fn item_columns(fields: &HashMap<String, String>, len: usize) -> HashMap<String, Vec<String>> {
+    fields
+        .iter()
+        .filter_map(|(name, value)| {
+            let values = serde_json::from_str::<Vec<String>>(value).ok()?;
+            (values.len() == len).then(|| (name.clone(), values))
+        })
+        .collect()
+}
+
+/// Items of an array field or the value of a single field
+fn values(fields: &HashMap<String, String>, key: &str) -> Vec<String> {
+    match fields.get(key) {
+        Some(v) => serde_json::from_str(v).unwrap_or_else(|_| vec![v.clone()]),
+        None => vec![],
+    }
+}
+
+fn first_value(fields: &HashMap<String, String>, key: &str) -> Option<String> {
+    values(fields, key)
+        .into_iter()
+        .map(|v| v.trim().to_string())
+        .find(|v| !v.is_empty())
+}
+
+/// length of an array field or the highest number of `1 / 12`
+fn page_count(fields: &HashMap<String, String>, key: &str) -> Option<u32> {
+    let v = fields.get(key)?;
+    if let Ok(items) = serde_json::from_str::<Vec<String>>(v) {
+        return Some(items.len() as u32);
+    }
+    v.split(|c: char| !c.is_ascii_digit())
+        .filter_map(|v| v.parse().ok())
+        .max()
+}
+
+/// `link` is on the same host and its path starts with all segments of `base`,
+/// so `/chapter-5` contains `/chapter-5/2` but not `/chapter-50`
+fn within(base: &str, link: &str) -> bool {
+    let (base, link) = match (Url::parse(base), Url::parse(link)) {
+        (Ok(base), Ok(link)) => (base, link),
+        _ => return false,
+    };
+    let segments = |url: &Url| {
+        url.path()
+            .split('/')
+            .filter(|v| !v.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let (base_path, link_path) = (segments(&base), segments(&link));
+    base.host_str() == link.host_str() && link_path.starts_with(&base_path)
+}
+
+fn resolve(base: &str, link: &str) -> Option<String> {
+    Url::parse(base)
+        .ok()?
+        .join(link)
+        .ok()
+        .map(|v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn within_compares_segments() {
+        let base = "https://example.com/manga/chapter-5";
+        assert!(within(base, "https://example.com/manga/chapter-5/2"));
+        assert!(within(base, "https://example.com/manga/chapter-5/?page=3"));
+        assert!(!within(base, "https://example.com/manga/chapter-50"));
+        assert!(!within(base, "https://example.com/manga/chapter-6/2"));
+        assert!(!within(base, "https://other.com/manga/chapter-5/2"));
+    }
+
+    fn fields(values: &[(&str, &[&str])]) -> HashMap<String, String> {
+        values
+            .iter()
+            .map(|(name, v)| (name.to_string(), serde_json::to_string(v).unwrap()))
+            .collect()
+    }
+
+    fn column(fields: &HashMap<String, String>, name: &str) -> Vec<String> {
+        serde_json::from_str(&fields[name]).unwrap()
+    }
+
+    #[test]
+    fn merge_keeps_fields_aligned() {
+        let mut all = fields(&[("urls", &["a", "b"]), ("labels", &["1", "2"])]);
+        let mut seen = values(&all, "urls").into_iter().collect();
+        let page = fields(&[
+            ("urls", &["b", "c", "d"]),
+            ("labels", &["2", "3", "4"]),
+            ("dates", &["x", "y", "z"]),
+        ]);
+        assert!(merge(&mut all, page, "urls", &mut seen));
+        let page = fields(&[("urls", &["e"])]);
+        assert!(merge(&mut all, page, "urls", &mut seen));
+        assert_eq!(column(&all, "urls"), ["a", "b", "c", "d", "e"]);
+        assert_eq!(column(&all, "labels"), ["1", "2", "3", "4", ""]);
+        assert_eq!(column(&all, "dates"), ["", "", "y", "z", ""]);
+    }
+
+    #[test]
+    fn merge_stops_without_new_items() {
+        let mut all = fields(&[("urls", &["a", "b"])]);
+        let mut seen = values(&all, "urls").into_iter().collect();
+        assert!(!merge(
+            &mut all,
+            fields(&[("urls", &["b", "a"])]),
+            "urls",
+            &mut seen
+        ));
+        assert!(!merge(
+            &mut all,
+            fields(&[("urls", &[])]),
+            "urls",
+            &mut seen
+        ));
+        assert_eq!(column(&all, "urls"), ["a", "b"]);
+    }
+}
@@ -0,0 +1,370 @@
+use crate::error::ScrapeError;
+use crate::pages::mangadex::MangaDexOptions;
+use crate::pages::mangaupdates::search::{OfflineDataset, ProgressFn, RecordPage, SearchRequest};
+use crate::services::icon::{get_uri, ExternalSite};
+use crate::services::init;
+use crate::services::metadata::merge::MergedMetadata;
+use crate::services::metadata::model::MangaMetadata;
+use crate::services::metadata::MetaDataService;
+use crate::services::multisite::{ChapterList, DuplicatePolicy, Info, MultiSiteService};
+use crate::services::search::{AggregatedSearch, SearchPage, SearchService};
+use crate::services::singlesite::SingleSiteService;
+use api_structure::scrape::ScrapeAccount;
+use api_structure::scraper::{ExternalSearchData, ValidSearches};
+use futures::future::join_all;
+use futures::Stream;
+use reqwest::{Client, Proxy};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Collects everything needed to create a [`Scraper`]
+pub struct ScraperBuilder {
+    root_folder: PathBuf,
+    user_agent: Option<String>,
+    proxy: Option<String>,
+    timeout: Option<Duration>,
+    cache_ttl: Option<Duration>,
+    max_concurrent_requests: Option<usize>,
+    metadata_priority: Vec<String>,
+    mal_client_id: Option<String>,
+    mangadex: MangaDexOptions,
+    duplicates: Vec<(String, DuplicatePolicy)>,
+    mangaupdates_port: Option<u16>,
+    mangaupdates_progress: Option<ProgressFn>,
+}
+
+impl ScraperBuilder {
+    /// `root_folder` has to contain the `external` folder with the filters, icons and scrapers
+    pub fn new(root_folder: impl Into<PathBuf>) -> Self {
+        Self {
+            root_folder: root_folder.into(),
+            user_agent: None,
+            proxy: None,
+            timeout: None,
+            cache_ttl: None,
+            max_concurrent_requests: None,
+            metadata_priority: vec![],
+            mal_client_id: None,
+            mangadex: Default::default(),
+            duplicates: vec![],
+            mangaupdates_port: None,
+            mangaupdates_progress: None,
+        }
+    }
+
+    pub fn user_agent(mut self, user_agent: impl ToString) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// http, https and socks5 proxies are supported
+    pub fn proxy(mut self, url: impl ToString) -> Self {
+        self.proxy = Some(url.to_string());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Keeps metadata and chapter lists in memory for `ttl`
+    pub fn cache(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Limits how many requests the [`Scraper`] handles at the same time
+    pub fn max_concurrent_requests(mut self, limit: usize) -> Self {
+        self.max_concurrent_requests = Some(limit);
+        self
+    }
+
+    /// Source uris in the order [`Scraper::merge_metadata`] prefers their values
+    pub fn metadata_priority(mut self, uris: Vec<String>) -> Self {
+        self.metadata_priority = uris;
+        self
+    }
+
+    /// Client id for the myanimelist v2 api. Without it the html pages are scraped
+    pub fn myanimelist_client_id(mut self, client_id: impl ToString) -> Self {
+        self.mal_client_id = Some(client_id.to_string());
+        self
+    }
+
+    /// Chapter languages and image quality used for mangadex
+    pub fn mangadex(mut self, options: MangaDexOptions) -> Self {
+        self.mangadex = options;
+        self
+    }
+
+    /// How colliding chapters of a built-in source like `mangadex` are handled,
+    /// `.scraper` and `.feed` files set it in their header
+    pub fn duplicate_policy(mut self, uri: impl ToString, policy: DuplicatePolicy) -> Self {
+        self.duplicates.push((uri.to_string(), policy));
+        self
+    }
+
+    /// Searches `external/mangaupdates.sql` as `mangaupdates-offline`.
+    /// The embedded postgres listens on `port` and starts with the first search
+    pub fn mangaupdates_offline(mut self, port: u16) -> Self {
+        self.mangaupdates_port = Some(port);
+        self
+    }
+
+    /// Reports the progress of the first import of the mangaupdates dump
+    pub fn mangaupdates_progress(mut self, progress: ProgressFn) -> Self {
+        self.mangaupdates_progress = Some(progress);
+        self
+    }
+
+    pub fn build(self) -> Result<Scraper, ScrapeError> {
+        let mut client = Client::builder();
+        if let Some(user_agent) = self.user_agent {
+            client = client.user_agent(user_agent);
+        }
+        if let Some(proxy) = self.proxy {
+            client = client.proxy(Proxy::all(proxy)?);
+        }
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(timeout);
+        }
+        let client = client.build()?;
+        let external =
+            ExternalSite::init(self.root_folder.clone()).map_err(ScrapeError::input_error)?;
+        let dataset = self.mangaupdates_port.map(|port| {
+            let dataset = OfflineDataset::new(self.root_folder.clone(), port);
+            match self.mangaupdates_progress {
+                Some(progress) => dataset.on_progress(progress),
+                None => dataset,
+            }
+        });
+        let (mut multi, single, search, meta) = init(self.root_folder)?;
+        for (uri, policy) in self.duplicates {
+            multi = multi.with_duplicates(uri, policy);
+        }
+        Ok(Scraper {
+            external: Arc::new(external),
+            multi: multi
+                .with_client(client.clone())
+                .with_mangadex(self.mangadex),
+            single: single.with_client(client.clone()),
+            search: search
+                .with_client(client.clone())
+                .with_mal_client_id(self.mal_client_id.clone())
+                .with_mangaupdates_offline(dataset),
+            meta: meta
+                .with_client(client)
+                .with_priority(self.metadata_priority)
+                .with_mal_client_id(self.mal_client_id),
+            limit: self.max_concurrent_requests.map(Semaphore::new),
+            metadata_cache: self.cache_ttl.map(TtlCache::new),
+            chapter_cache: self.cache_ttl.map(TtlCache::new),
+        })
+    }
+}
+
+/// Combines all services and owns the registered [`ExternalSite`]s
+pub struct Scraper {
+    external: Arc<Vec<ExternalSite>>,
+    multi: MultiSiteService,
+    single: SingleSiteService,
+    search: SearchService,
+    meta: MetaDataService,
+    limit: Option<Semaphore>,
+    metadata_cache: Option<TtlCache<MangaMetadata>>,
+    chapter_cache: Option<TtlCache<ChapterList>>,
+}
+
+impl Scraper {
+    pub fn builder(root_folder: impl Into<PathBuf>) -> ScraperBuilder {
+        ScraperBuilder::new(root_folder)
+    }
+
+    pub fn external_sites(&self) -> &[ExternalSite] {
+        &self.external
+    }
+
+    /// Path of the icon registered for the site the url belongs to
+    pub fn icon_for(&self, url: &str) -> Option<&Path> {
+        self.external
+            .iter()
+            .find(|v| v.check(url))
+            .map(|v| v.path_buf.as_path())
+    }
+
+    pub fn search_sites(&self) -> HashMap<String, ValidSearches> {
+        self.search.sites()
+    }
+
+    pub async fn search(
+        &self,
+        uri: &str,
+        search: ExternalSearchData,
+    ) -> Result<SearchPage, ScrapeError> {
+        self.limited(self.search.search(uri, search)).await
+    }
+
+    /// Searches multiple sites at once, an empty `sites` searches all of them.
+    /// Every site counts as one request of `max_concurrent_requests`
+    pub fn search_stream(
+        &self,
+        query: &str,
+        page: u32,
+        sites: &[String],
+        timeout: Duration,
+    ) -> impl Stream<Item = (String, Result<SearchPage, ScrapeError>)> + '_ {
+        self.search
+            .limited_stream(query, page, sites, timeout, self.limit.as_ref())
+    }
+
+    /// [`Scraper::search_stream`] with the results grouped by series
+    pub async fn search_all(
+        &self,
+        query: &str,
+        page: u32,
+        sites: &[String],
+        timeout: Duration,
+    ) -> AggregatedSearch {
+        AggregatedSearch::collect(self.search_stream(query, page, sites, timeout)).await
+    }
+
+    /// Search of `mangaupdates-offline` with the full rows, see [`ScraperBuilder::mangaupdates_offline`]
+    pub async fn mangaupdates_offline(
+        &self,
+        request: &SearchRequest,
+        page: u32,
+    ) -> Result<RecordPage, ScrapeError> {
+        match self.search.mangaupdates_offline() {
+            Some(dataset) => self.limited(dataset.search(request, page)).await,
+            None => Err(ScrapeError::input_error(
+                "mangaupdates-offline is not enabled",
+            )),
+        }
+    }
+
+    /// Starts the embedded database of `mangaupdates-offline` and imports the dump if needed.
+    /// Until then `mangaupdates-offline` is left out of searches over all sites
+    pub async fn start_mangaupdates_offline(&self) -> Result<(), ScrapeError> {
+        match self.search.mangaupdates_offline() {
+            Some(dataset) => dataset.start().await,
+            None => Err(ScrapeError::input_error(
+                "mangaupdates-offline is not enabled",
+            )),
+        }
+    }
+
+    /// Stops the embedded database of `mangaupdates-offline` if it is running
+    pub async fn shutdown(&self) -> Result<(), ScrapeError> {
+        match self.search.mangaupdates_offline() {
+            Some(dataset) => dataset.stop().await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn metadata(&self, url: &str) -> Result<MangaMetadata, ScrapeError> {
+        if let Some(v) = self.metadata_cache.as_ref().and_then(|c| c.get(url)) {
+            return Ok(v);
+        }
+        let data = self
+            .limited(self.meta.get_metadata(url, self.external.clone()))
+            .await?;
+        if let Some(cache) = &self.metadata_cache {
+            cache.insert(url, data.clone());
+        }
+        Ok(data)
+    }
+
+    /// Metadata of the same series from multiple sites,
+    /// every url counts against [`ScraperBuilder::max_concurrent_requests`]
+    pub async fn merge_metadata(&self, urls: &[String]) -> Result<MergedMetadata, ScrapeError> {
+        let results = join_all(urls.iter().map(|url| async move {
+            let uri = get_uri(&self.external, url)?;
+            Ok((uri, self.metadata(url).await?))
+        }))
+        .await;
+        self.meta.merge_results(urls, results)
+    }
+
+    /// Returns the released and the scheduled chapters
+    pub async fn chapters(&self, url: &str) -> Result<ChapterList, ScrapeError> {
+        if let Some(v) = self.chapter_cache.as_ref().and_then(|c| c.get(url)) {
+            return Ok(v);
+        }
+        let data = self
+            .limited(self.multi.get_chapters(url, self.external.clone()))
+            .await?;
+        if let Some(cache) = &self.chapter_cache {
+            cache.insert(url, data.clone());
+        }
+        Ok(data)
+    }
+
+    /// Skips the cache, used to look for updates
+    pub(crate) async fn fresh_chapters(&self, url: &str) -> Result<ChapterList, ScrapeError> {
+        self.limited(self.multi.get_chapters(url, self.external.clone()))
+            .await
+    }
+
+    /// Pages of a chapter returned by [`Scraper::chapters`]
+    pub async fn pages(
+        &self,
+        info: Info,
+        acc: Option<ScrapeAccount>,
+    ) -> Result<Vec<String>, ScrapeError> {
+        self.limited(self.multi.get_pages(info, acc)).await
+    }
+
+    /// Pages of a site that only hosts a single chapter/gallery
+    pub async fn single_pages(&self, url: &str) -> Result<Vec<String>, ScrapeError> {
+        self.limited(self.single.get_pages(url, self.external.clone()))
+            .await
+    }
+
+    async fn limited<T>(&self, fut: impl Future<Output = T>) -> T {
+        match &self.limit {
+            Some(semaphore) => {
+                let _permit = semaphore.acquire().await;
+                fut.await
+            }
+            None => fut.await,
+        }
+    }
+}
+
+struct TtlCache<T> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, T)>>,
+}
+
+impl<T: Clone> TtlCache<T> {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<T> {
+        let mut entries = self.entries.lock().ok()?;
+        match entries.get(key) {
+            Some((time, v)) if time.elapsed() < self.ttl => Some(v.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: &str, value: T) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|_, (time, _)| time.elapsed() < self.ttl);
+            entries.insert(key.to_string(), (Instant::now(), value));
+        }
+    }
+}
@@ -1,14 +1,38 @@
 use crate::extractor::SearchServiceScrapeData;
-use crate::pages::{anilist, animeplanet, kitsu};
+use crate::pages::mangaupdates::query::parse_query;
+use crate::pages::mangaupdates::search::OfflineDataset;
+use crate::pages::{anilist, animeplanet, kitsu, mangadex, myanimelist};
 use crate::ScrapeError;
-use api_structure::scraper::{ExternalSearchData, ScrapeSearchResult, ValidSearch, ValidSearches};
+use api_structure::scraper::{
+    ExternalSearchData, ScrapeSearchResult, SimpleSearch, ValidSearch, ValidSearches,
+};
+use futures::stream::FuturesUnordered;
+use futures::{Stream, StreamExt};
 use reqwest::Client;
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// One page of search results
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SearchPage {
+    pub results: Vec<ScrapeSearchResult>,
+    pub page: u32,
+    /// guessed from the amount of results if the site doesn't tell
+    pub has_next: bool,
+    /// total amount of results, if the site reports it
+    pub total: Option<u64>,
+    /// result url => (uri => id) of the same series on other sites, if the site links them
+    pub external_ids: HashMap<String, HashMap<String, String>>,
+}
 
 #[derive(Default)]
 pub struct SearchService {
     client: Client,
     services: HashMap<String, SearchServiceScrapeData>,
+    mal_client_id: Option<String>,
+    mangaupdates: Option<OfflineDataset>,
 }
 
 impl SearchService {
@@ -16,9 +40,38 @@ impl SearchService {
         Self {
             client: Default::default(),
             services,
+            mal_client_id: None,
+            mangaupdates: None,
         }
     }
 
+    pub(crate) fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// enables the myanimelist v2 api instead of scraping the html page
+    pub fn with_mal_client_id(mut self, client_id: Option<String>) -> Self {
+        self.mal_client_id = client_id;
+        self
+    }
+
+    /// searches the local mangaupdates dump as `mangaupdates-offline`
+    pub fn with_mangaupdates_offline(mut self, dataset: Option<OfflineDataset>) -> Self {
+        self.mangaupdates = dataset;
+        self
+    }
+
+    pub fn mangaupdates_offline(&self) -> Option<&OfflineDataset> {
+        self.mangaupdates.as_ref()
+    }
+
+    fn offline_started(&self) -> bool {
+        self.mangaupdates
+            .as_ref()
+            .is_some_and(|dataset| dataset.is_started())
+    }
+
     pub fn sites(&self) -> HashMap<String, ValidSearches> {
         let mut keys = vec![
             (
@@ -33,15 +86,25 @@ impl SearchService {
                 "anime-planet".to_string(),
                 ValidSearches::ValidSearch(animeplanet::get_valid()),
             ),
+            ("myanimelist".to_string(), ValidSearches::String),
+            (
+                "mangadex".to_string(),
+                ValidSearches::ValidSearch(mangadex::get_valid()),
+            ),
         ];
         keys.append(
             &mut self
                 .services
-                .keys()
-                .cloned()
-                .map(|v| (v, ValidSearches::String))
+                .iter()
+                .map(|(uri, service)| match service.valid() {
+                    Some(valid) => (uri.clone(), ValidSearches::ValidSearch(valid)),
+                    None => (uri.clone(), ValidSearches::String),
+                })
                 .collect::<Vec<_>>(),
         );
+        if self.mangaupdates.is_some() {
+            keys.push(("mangaupdates-offline".to_string(), ValidSearches::String));
+        }
         keys.into_iter().collect()
     }
 
@@ -49,17 +112,368 @@ impl SearchService {
         &self,
         uri: &str,
         search: ExternalSearchData,
-    ) -> Result<Vec<ScrapeSearchResult>, ScrapeError> {
+    ) -> Result<SearchPage, ScrapeError> {
         if let Some(service) = self.services.get(uri) {
-            let (query, page) = search.get_query();
-            service.search(&self.client, query, page).await
+            service.search(&self.client, search).await
         } else {
             match uri {
-                "anilist" => anilist::search(&self.client, &search.get_simple()?).await,
+                "anilist" => anilist::search(&self.client, search.get_simple()?).await,
                 "kitsu" => kitsu::search(&self.client, search.get_simple()?).await,
                 "anime-planet" => animeplanet::search(&self.client, search.get_simple()?).await,
+                "mangadex" => mangadex::search(&self.client, search.get_simple()?).await,
+                "mangaupdates-offline" => match &self.mangaupdates {
+                    Some(dataset) => {
+                        let (query, page) = search.get_query();
+                        let request = parse_query(&query)?;
+                        dataset.results(&request, page).await
+                    }
+                    None => Err(ScrapeError::input_error("uri does not exist")),
+                },
+                "myanimelist" => {
+                    let (query, page) = search.get_query();
+                    let client_id = self.mal_client_id.as_deref();
+                    myanimelist::search(&self.client, query, page, client_id).await
+                }
                 _ => Err(ScrapeError::input_error("uri does not exist")),
             }
         }
     }
+
+    /// Searches all `sites` at once and yields the results of each site when it's done.
+    /// An empty `sites` searches every site, `mangaupdates-offline` only once it's started,
+    /// so the timeout doesn't cancel the import of the dump.
+    pub fn search_stream(
+        &self,
+        query: &str,
+        page: u32,
+        sites: &[String],
+        timeout: Duration,
+    ) -> impl Stream<Item = (String, Result<SearchPage, ScrapeError>)> + '_ {
+        self.limited_stream(query, page, sites, timeout, None)
+    }
+
+    /// [`SearchService::search_stream`] that takes a permit of `limit` per site,
+    /// the timeout starts once the site has its permit
+    pub(crate) fn limited_stream<'a>(
+        &'a self,
+        query: &str,
+        page: u32,
+        sites: &[String],
+        timeout: Duration,
+        limit: Option<&'a Semaphore>,
+    ) -> impl Stream<Item = (String, Result<SearchPage, ScrapeError>)> + 'a {
+        let valid = self.sites();
+        let sites = match sites.is_empty() {
+            true => valid
+                .keys()
+                .filter(|uri| uri.as_str() != "mangaupdates-offline" || self.offline_started())
+                .cloned()
+                .collect(),
+            false => sites.to_vec(),
+        };
+        sites
+            .into_iter()
+            .map(|uri| {
+                let data = match valid.get(&uri) {
+                    Some(ValidSearches::ValidSearch(_)) => {
+                        ExternalSearchData::Simple(SimpleSearch {
+                            search: query.to_string(),
+                            sort: None,
+                            desc: false,
+                            status: None,
+                            tags: vec![],
+                            page,
+                        })
+                    }
+                    _ => ExternalSearchData::String((query.to_string(), page)),
+                };
+                async move {
+                    let _permit = match limit {
+                        Some(semaphore) => semaphore.acquire().await.ok(),
+                        None => None,
+                    };
+                    let res = match tokio::time::timeout(timeout, self.search(&uri, data)).await {
+                        Ok(v) => v,
+                        Err(_) => Err(ScrapeError::timeout(format!("search of {uri} timed out"))),
+                    };
+                    (uri, res)
+                }
+            })
+            .collect::<FuturesUnordered<_>>()
+    }
+
+    /// [`SearchService::search_stream`] with the results grouped by series
+    pub async fn search_all(
+        &self,
+        query: &str,
+        page: u32,
+        sites: &[String],
+        timeout: Duration,
+    ) -> AggregatedSearch {
+        AggregatedSearch::collect(self.search_stream(query, page, sites, timeout)).await
+    }
+}
+
+/// Results of different sites that most likely are the same series
+#[derive(Debug, Default)]
+pub struct SearchGroup {
+    pub title: String,
+    /// (uri, result)
+    pub results: Vec<(String, ScrapeSearchResult)>,
+    /// uri => id of the series on the sites of the results and the sites they link to
+    pub external_ids: HashMap<String, String>,
+    /// titles, covers and ids of the results
+    keys: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct AggregatedSearch {
+    pub groups: Vec<SearchGroup>,
+    /// uri => error
+    pub failed: HashMap<String, ScrapeError>,
+}
+
+impl AggregatedSearch {
+    /// Groups the results of a [`SearchService::search_stream`]
+    pub async fn collect(
+        stream: impl Stream<Item = (String, Result<SearchPage, ScrapeError>)>,
+    ) -> Self {
+        let mut res = AggregatedSearch::default();
+        let mut stream = std::pin::pin!(stream);
+        while let Some((uri, result)) = stream.next().await {
+            match result {
+                Ok(v) => res.add(&uri, v),
+                Err(e) => {
+                    res.failed.insert(uri, e);
+                }
+            }
+        }
+        res
+    }
+
+    /// Results are grouped if they share their normalized title, cover or the id of a site.
+    /// Ids are read from the result urls and from the links in [`SearchPage::external_ids`].
+    /// Covers are compared by url without the query, they aren't downloaded to hash them.
+    /// A cover that is used by several results of the page is a placeholder and ignored.
+    /// A result that matches several groups joins them into one.
+    /// Can be used to group the items of [`SearchService::search_stream`] as they arrive.
+    pub fn add(&mut self, uri: &str, page: SearchPage) {
+        let SearchPage {
+            results,
+            mut external_ids,
+            ..
+        } = page;
+        let mut covers: HashMap<&str, usize> = HashMap::new();
+        for result in &results {
+            *covers.entry(cover_key(&result.cover)).or_default() += 1;
+        }
+        let placeholders = covers
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(cover, _)| cover.to_string())
+            .collect::<HashSet<_>>();
+        for result in results {
+            let mut ids = external_ids.remove(&result.url).unwrap_or_default();
+            if let Some(id) = url_id(&result.url) {
+                ids.entry(site_uri(uri).to_string()).or_insert(id);
+            }
+            let mut keys = ids
+                .iter()
+                .map(|(uri, id)| format!("{uri}:{}", id.to_lowercase()))
+                .collect::<HashSet<_>>();
+            let title = title_key(&result.title);
+            if !title.is_empty() {
+                keys.insert(format!("title:{title}"));
+            }
+            let cover = cover_key(&result.cover);
+            if !cover.is_empty() && !placeholders.contains(cover) {
+                keys.insert(format!("cover:{cover}"));
+            }
+            let matching = (0..self.groups.len())
+                .filter(|i| !self.groups[*i].keys.is_disjoint(&keys))
+                .collect::<Vec<_>>();
+            let index = match matching.first() {
+                Some(i) => *i,
+                None => {
+                    self.groups.push(SearchGroup {
+                        title: result.title.clone(),
+                        ..Default::default()
+                    });
+                    self.groups.len() - 1
+                }
+            };
+            // removed from the back, so the indices before stay valid
+            for i in matching.into_iter().skip(1).rev() {
+                let other = self.groups.remove(i);
+                let group = &mut self.groups[index];
+                group.results.extend(other.results);
+                group.keys.extend(other.keys);
+                for (uri, id) in other.external_ids {
+                    group.external_ids.entry(uri).or_insert(id);
+                }
+            }
+            let group = &mut self.groups[index];
+            group.keys.extend(keys);
+            for (uri, id) in ids {
+                group.external_ids.entry(uri).or_insert(id);
+            }
+            group.results.push((uri.to_string(), result));
+        }
+    }
+}
+
+/// Id in urls like `/manga/{id}`, `/title/{id}` or `/series/{id}`
+fn url_id(url: &str) -> Option<String> {
+    let mut segments = url.split(['/', '?', '#']);
+    segments.find(|v| matches!(*v, "manga" | "title" | "series"))?;
+    segments
+        .next()
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_string())
+}
+
+fn cover_key(cover: &str) -> &str {
+    cover.split(['?', '#']).next().unwrap_or_default()
+}
+
+/// The offline dump has the same ids as the site
+fn site_uri(uri: &str) -> &str {
+    match uri {
+        "mangaupdates-offline" => "manga-updates",
+        _ => uri,
+    }
+}
+
+/// "The Promised Neverland!" and "promised neverland" share the same key
+fn title_key(title: &str) -> String {
+    let title = title.trim().to_lowercase();
+    title
+        .strip_prefix("the ")
+        .unwrap_or(&title)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(title: &str, url: &str, cover: &str) -> ScrapeSearchResult {
+        ScrapeSearchResult {
+            title: title.to_string(),
+            url: url.to_string(),
+            cover: cover.to_string(),
+            r#type: None,
+            status: None,
+        }
+    }
+
+    fn page(results: Vec<ScrapeSearchResult>) -> SearchPage {
+        SearchPage {
+            results,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn groups_by_title_cover_and_ids() {
+        let mut res = AggregatedSearch::default();
+        res.add(
+            "anilist",
+            page(vec![
+                result("Shingeki no Kyojin", "https://anilist.co/manga/53390", ""),
+                result(
+                    "Berserk",
+                    "https://anilist.co/manga/30002",
+                    "https://a/b.jpg",
+                ),
+            ]),
+        );
+        res.add(
+            "kitsu",
+            page(vec![result(
+                "Kenpuu Denki",
+                "https://kitsu.io/manga/x",
+                "https://a/b.jpg?v=2",
+            )]),
+        );
+        let mut mangadex = page(vec![result(
+            "Attack on Titan",
+            "https://mangadex.org/title/304ceac3",
+            "",
+        )]);
+        mangadex.external_ids.insert(
+            "https://mangadex.org/title/304ceac3".to_string(),
+            [("anilist".to_string(), "53390".to_string())].into(),
+        );
+        res.add("mangadex", mangadex);
+        assert_eq!(res.groups.len(), 2);
+        let titan = &res.groups[0];
+        assert_eq!(titan.results.len(), 2);
+        assert_eq!(titan.external_ids["mangadex"], "304ceac3");
+        assert_eq!(res.groups[1].results.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn every_site_takes_a_permit() {
+        let service = SearchService::default();
+        let limit = Semaphore::new(1);
+        let sites = ["a".to_string(), "b".to_string()];
+        let stream = service.limited_stream("x", 1, &sites, Duration::from_secs(1), Some(&limit));
+        let res = AggregatedSearch::collect(stream).await;
+        assert_eq!(res.failed.len(), 2);
+        assert_eq!(limit.available_permits(), 1);
+    }
+
+    #[test]
+    fn placeholder_covers_are_ignored() {
+        let mut res = AggregatedSearch::default();
+        res.add(
+            "asura",
+            page(vec![
+                result("Solo Leveling", "https://a/series/1", "https://a/none.png"),
+                result(
+                    "Omniscient Reader",
+                    "https://a/series/2",
+                    "https://a/none.png?v=1",
+                ),
+            ]),
+        );
+        res.add(
+            "kitsu",
+            page(vec![result(
+                "Na Honjaman Level Up",
+                "https://kitsu.io/manga/x",
+                "https://a/none.png",
+            )]),
+        );
+        assert_eq!(res.groups.len(), 3);
+    }
+
+    #[test]
+    fn bridging_result_joins_groups() {
+        let mut res = AggregatedSearch::default();
+        res.add(
+            "anilist",
+            page(vec![result("A", "https://anilist.co/manga/1", "")]),
+        );
+        res.add(
+            "myanimelist",
+            page(vec![result("B", "https://myanimelist.net/manga/2", "")]),
+        );
+        assert_eq!(res.groups.len(), 2);
+        let mut mangadex = page(vec![result("C", "https://mangadex.org/title/3", "")]);
+        mangadex.external_ids.insert(
+            "https://mangadex.org/title/3".to_string(),
+            [
+                ("anilist".to_string(), "1".to_string()),
+                ("myanimelist".to_string(), "2".to_string()),
+            ]
+            .into(),
+        );
+        res.add("mangadex", mangadex);
+        assert_eq!(res.groups.len(), 1);
+        assert_eq!(res.groups[0].results.len(), 3);
+    }
 }
@@ -1,8 +1,8 @@
-use crate::downloader::download;
 use crate::error::ScrapeError;
 use crate::pages;
 use crate::services::icon::{get_uri, ExternalSite};
-use crate::services::{config_to_request_builder, Service};
+use crate::services::pagination::Paginate;
+use crate::services::{pagination, Service};
 use reqwest::Client;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
@@ -23,6 +23,11 @@ impl SingleSiteService {
         }
     }
 
+    pub(crate) fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
     pub async fn get_pages(
         &self,
         url: &str,
@@ -30,9 +35,8 @@ impl SingleSiteService {
     ) -> Result<Vec<String>, ScrapeError> {
         let uri = get_uri(&data, url)?;
         if let Some(v) = self.services.get(&uri) {
-            let req = config_to_request_builder(&self.client, &v.config, url);
-            let html = download(req).await?;
-            let fields = v.process(html.as_str());
+            let fields =
+                pagination::fetch_all(&self.client, v, url, "imgs", Paginate::Pages).await?;
             post_process(&uri, fields)
         } else {
             manual(&uri, url).await
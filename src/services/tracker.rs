@@ -0,0 +1,353 @@
+use crate::error::ScrapeError;
+use crate::extractor::date;
+use crate::services::multisite::Info;
+use crate::services::scraper::Scraper;
+use futures::future::join_all;
+use rand::Rng;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// Chapters are identified by their url
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChapterDiff {
+    pub site: String,
+    pub url: String,
+    pub added: Vec<Info>,
+    pub removed: Vec<Info>,
+    /// (old, new)
+    pub changed: Vec<(Info, Info)>,
+}
+
+impl ChapterDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Tracked {
+    site: String,
+    /// released and scheduled chapters
+    chapters: Vec<Info>,
+    last_check: Option<i64>,
+    next_check: i64,
+}
+
+/// Remembers the chapters of followed series in a json file
+/// and reports what changed since the last check
+pub struct UpdateTracker {
+    scraper: Arc<Scraper>,
+    store: PathBuf,
+    series: Mutex<HashMap<String, Tracked>>,
+    interval: Duration,
+    jitter: Duration,
+    per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    /// generation of the latest snapshot and of the one on disk
+    generation: AtomicU64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl UpdateTracker {
+    /// Loads `store` if it exists
+    pub fn new(scraper: Arc<Scraper>, store: impl Into<PathBuf>) -> Result<Self, ScrapeError> {
+        let store = store.into();
+        let series = match store.exists() {
+            true => serde_json::from_str(&fs::read_to_string(&store)?)?,
+            false => HashMap::new(),
+        };
+        Ok(Self {
+            scraper,
+            store,
+            series: Mutex::new(series),
+            interval: Duration::from_secs(60 * 60),
+            jitter: Duration::from_secs(10 * 60),
+            per_host: 2,
+            hosts: Mutex::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+            written: Arc::new(Mutex::new(0)),
+        })
+    }
+
+    /// Time between two checks of a series
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Random delay added to `interval` so series of the same site aren't checked at once
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Concurrent checks per host
+    pub fn per_host_limit(mut self, limit: usize) -> Self {
+        self.per_host = limit.max(1);
+        self
+    }
+
+    pub fn follow(&self, url: &str) -> Result<(), ScrapeError> {
+        let site = self
+            .scraper
+            .external_sites()
+            .iter()
+            .find(|v| v.check(url))
+            .map(|v| v.uri.clone())
+            .ok_or(ScrapeError::input_error("no site registered for url"))?;
+        let mut series = self.series.lock().unwrap();
+        series.entry(url.to_string()).or_insert(Tracked {
+            site,
+            chapters: vec![],
+            last_check: None,
+            next_check: date::now(),
+        });
+        let (generation, json) = self.snapshot(&series)?;
+        drop(series);
+        write_store(&self.store, &self.written, generation, json)
+    }
+
+    pub fn unfollow(&self, url: &str) -> Result<bool, ScrapeError> {
+        let mut series = self.series.lock().unwrap();
+        let removed = series.remove(url).is_some();
+        let (generation, json) = self.snapshot(&series)?;
+        drop(series);
+        write_store(&self.store, &self.written, generation, json)?;
+        Ok(removed)
+    }
+
+    pub fn followed(&self) -> Vec<String> {
+        self.series.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Last known chapters of a followed series
+    pub fn chapters(&self, url: &str) -> Option<Vec<Info>> {
+        self.series
+            .lock()
+            .unwrap()
+            .get(url)
+            .map(|v| v.chapters.clone())
+    }
+
+    /// Scrapes the series now, the first check reports every chapter as added
+    pub async fn check(&self, url: &str) -> Result<ChapterDiff, ScrapeError> {
+        let res = self.update(url).await;
+        self.persist().await?;
+        res
+    }
+
+    /// [`UpdateTracker::check`] without saving the store
+    async fn update(&self, url: &str) -> Result<ChapterDiff, ScrapeError> {
+        let site = match self.series.lock().unwrap().get(url) {
+            Some(v) => v.site.clone(),
+            None => return Err(ScrapeError::input_error("url is not followed")),
+        };
+        let semaphore = self.host_limit(url);
+        let res = {
+            let _permit = semaphore.acquire().await;
+            self.scraper.fresh_chapters(url).await
+        };
+        let mut series = self.series.lock().unwrap();
+        let tracked = match series.get_mut(url) {
+            Some(v) => v,
+            // unfollowed while checking
+            None => return Err(ScrapeError::input_error("url is not followed")),
+        };
+        tracked.next_check = self.next_check();
+        let list = res?;
+        let chapters = list.now.into_iter().chain(list.later).collect::<Vec<_>>();
+        let diff = diff(site, url, &tracked.chapters, &chapters);
+        tracked.chapters = chapters;
+        tracked.last_check = Some(date::now());
+        Ok(diff)
+    }
+
+    /// Checks every series that is due, the store is saved once all checks are done
+    pub async fn poll(&self) -> Vec<(String, Result<ChapterDiff, ScrapeError>)> {
+        let now = date::now();
+        let due = self
+            .series
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, v)| v.next_check <= now)
+            .map(|(url, _)| url.clone())
+            .collect::<Vec<_>>();
+        if due.is_empty() {
+            return vec![];
+        }
+        let res = join_all(due.into_iter().map(|url| async move {
+            let res = self.update(&url).await;
+            (url, res)
+        }))
+        .await;
+        // the changes stay in memory, the next save writes them
+        if let Err(e) = self.persist().await {
+            log::error!("failed to save {}: {:?}", self.store.display(), e);
+        }
+        res
+    }
+
+    /// Time until the next series is due
+    pub fn next_due(&self) -> Option<Duration> {
+        let next = self
+            .series
+            .lock()
+            .unwrap()
+            .values()
+            .map(|v| v.next_check)
+            .min()?;
+        Some(Duration::from_secs((next - date::now()).max(0) as u64))
+    }
+
+    /// Polls forever, `on_update` gets every non empty diff and every error
+    pub async fn run(&self, mut on_update: impl FnMut(&str, Result<ChapterDiff, ScrapeError>)) {
+        loop {
+            for (url, res) in self.poll().await {
+                if !matches!(&res, Ok(v) if v.is_empty()) {
+                    on_update(&url, res);
+                }
+            }
+            let wait = self
+                .next_due()
+                .unwrap_or(self.interval)
+                .clamp(Duration::from_secs(1), self.interval);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    fn next_check(&self) -> i64 {
+        let jitter = rand::thread_rng().gen_range(0..=self.jitter.as_secs());
+        date::now() + (self.interval.as_secs() + jitter) as i64
+    }
+
+    fn host_limit(&self, url: &str) -> Arc<Semaphore> {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|v| v.host_str().map(|v| v.to_string()))
+            .unwrap_or_default();
+        self.hosts
+            .lock()
+            .unwrap()
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone()
+    }
+
+    /// Called with the lock held, so a newer generation has newer content
+    fn snapshot(&self, series: &HashMap<String, Tracked>) -> Result<(u64, String), ScrapeError> {
+        let json = serde_json::to_string(series)?;
+        Ok((self.generation.fetch_add(1, Ordering::Relaxed) + 1, json))
+    }
+
+    /// Writes the store on the blocking pool, outside of the lock
+    async fn persist(&self) -> Result<(), ScrapeError> {
+        let (generation, json) = self.snapshot(&self.series.lock().unwrap())?;
+        let store = self.store.clone();
+        let written = self.written.clone();
+        tokio::task::spawn_blocking(move || write_store(&store, &written, generation, json))
+            .await
+            .map_err(io::Error::other)?
+    }
+}
+
+/// Snapshots that are older than the one on disk are skipped
+fn write_store(
+    store: &Path,
+    written: &Mutex<u64>,
+    generation: u64,
+    json: String,
+) -> Result<(), ScrapeError> {
+    let mut written = written.lock().unwrap();
+    if *written >= generation {
+        return Ok(());
+    }
+    let tmp = store.with_extension("tmp");
+    fs::write(&tmp, json)?;
+    fs::rename(tmp, store)?;
+    *written = generation;
+    Ok(())
+}
+
+fn diff(site: String, url: &str, old: &[Info], new: &[Info]) -> ChapterDiff {
+    let old_urls = old
+        .iter()
+        .map(|v| (v.url.as_str(), v))
+        .collect::<HashMap<_, _>>();
+    let new_urls = new.iter().map(|v| v.url.as_str()).collect::<HashSet<_>>();
+    let mut res = ChapterDiff {
+        site,
+        url: url.to_string(),
+        ..Default::default()
+    };
+    for info in new {
+        match old_urls.get(info.url.as_str()) {
+            None => res.added.push(info.clone()),
+            Some(old) if changed(old, info) => res.changed.push(((*old).clone(), info.clone())),
+            Some(_) => {}
+        }
+    }
+    res.removed = old
+        .iter()
+        .filter(|v| !new_urls.contains(v.url.as_str()))
+        .cloned()
+        .collect();
+    res
+}
+
+/// Page counts and dates are often only shown on some pages or change their format,
+/// so only what identifies the chapter counts as a change. Both have the same url
+fn changed(old: &Info, new: &Info) -> bool {
+    old.episode != new.episode
+        || old.titles != new.titles
+        || old.groups != new.groups
+        || old.locked != new.locked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(url: &str, episode: f64) -> Info {
+        Info {
+            url: url.to_string(),
+            episode,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn only_stable_fields_change() {
+        let old = vec![info("a", 1.0), info("b", 2.0), info("c", 3.0)];
+        let mut new = old.clone();
+        new[0].page_count = Some(20);
+        new[0].published_at = Some("2 days ago".to_string());
+        new[1].locked = true;
+        new.remove(2);
+        new.push(info("d", 4.0));
+        let res = diff("site".to_string(), "url", &old, &new);
+        assert_eq!(res.changed.len(), 1);
+        assert_eq!(res.changed[0].1.url, "b");
+        assert_eq!(res.added[0].url, "d");
+        assert_eq!(res.removed[0].url, "c");
+    }
+
+    #[test]
+    fn older_snapshots_are_skipped() {
+        let store = std::env::temp_dir().join(format!("tracker-{}.json", std::process::id()));
+        let written = Mutex::new(0);
+        write_store(&store, &written, 2, "new".to_string()).unwrap();
+        write_store(&store, &written, 1, "old".to_string()).unwrap();
+        assert_eq!(fs::read_to_string(&store).unwrap(), "new");
+        assert_eq!(*written.lock().unwrap(), 2);
+        fs::remove_file(store).unwrap();
+    }
+}